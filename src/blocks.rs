@@ -0,0 +1,190 @@
+use simple_matrix::Matrix;
+use lazy_static::lazy_static;
+
+
+pub const BLOCK_SIZE: usize = 4;
+
+pub type BlockTypeProto = Vec<bool>;
+pub type BlockType = Matrix<bool>;
+
+// in the same order as BLOCKS
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlockKind {
+	Smashboy,
+	OrangeRicky,
+	BlueRicky,
+	ClevelandZ,
+	RhodeIslandZ,
+	Hero,
+	Teewee
+}
+
+pub const BLOCK_KINDS: [BlockKind; 7] = [BlockKind::Smashboy, BlockKind::OrangeRicky,
+	BlockKind::BlueRicky, BlockKind::ClevelandZ, BlockKind::RhodeIslandZ,
+	BlockKind::Hero, BlockKind::Teewee];
+
+impl BlockKind {
+	// guideline letter, used when writing stages as text
+	pub fn letter(self) -> char {
+		match self {
+			BlockKind::Smashboy => 'O',
+			BlockKind::OrangeRicky => 'L',
+			BlockKind::BlueRicky => 'J',
+			BlockKind::ClevelandZ => 'Z',
+			BlockKind::RhodeIslandZ => 'S',
+			BlockKind::Hero => 'I',
+			BlockKind::Teewee => 'T'
+		}
+	}
+
+	// side of the square the piece rotates in
+	pub fn box_size(self) -> usize {
+		match self {
+			BlockKind::Smashboy | BlockKind::Hero => 4,
+			_ => 3
+		}
+	}
+}
+
+
+lazy_static! {
+/*
+🧙🍔🍔🧙
+🧙🍔🍔🧙
+🧙🧙🧙🧙
+🧙🧙🧙🧙
+*/
+ pub static ref SMASHBOY_BLOCK: BlockTypeProto =
+	vec![false, true, true, false,
+	 false, true, true, false,
+	 false, false, false, false,
+	 false, false, false, false];
+
+
+/*
+🧙🧙🍔🧙
+🍔🍔🍔🧙
+🧙🧙🧙🧙
+🧙🧙🧙🧙
+*/
+pub static ref ORANGE_RICKY_BLOCK: BlockTypeProto =
+	vec![false, false, true, false,
+	 true, true, true, false,
+	 false, false, false, false,
+	 false, false, false, false];
+
+/*
+🍔🧙🧙🧙
+🍔🍔🍔🧙
+🧙🧙🧙🧙
+🧙🧙🧙🧙
+*/
+pub static ref BLUE_RICKY_BLOCK: BlockTypeProto =
+	vec![true, false, false, false,
+	 true, true, true, false,
+	 false, false, false, false,
+	 false, false, false, false];
+
+/*
+🍔🍔🧙🧙
+🧙🍔🍔🧙
+🧙🧙🧙🧙
+🧙🧙🧙🧙
+*/
+pub static ref CLEVELAND_Z_BLOCK: BlockTypeProto =
+	vec![true, true, false, false,
+	 false, true, true, false,
+	 false, false, false, false,
+	 false, false, false, false];
+
+/*
+🧙🍔🍔🧙
+🍔🍔🧙🧙
+🧙🧙🧙🧙
+🧙🧙🧙🧙
+*/
+pub static ref RHODE_ISLAND_Z_BLOCK: BlockTypeProto =
+	vec![false, true, true, false,
+	 true, true, false, false,
+	 false, false, false, false,
+	 false, false, false, false];
+
+/*
+🧙🧙🧙🧙
+🍔🍔🍔🍔
+🧙🧙🧙🧙
+🧙🧙🧙🧙
+*/
+pub static ref HERO_BLOCK: BlockTypeProto =
+	vec![false, false, false, false,
+	true, true, true, true,
+	false, false, false, false,
+	false, false, false, false];
+
+/*
+🧙🍔🧙🧙
+🍔🍔🍔🧙
+🧙🧙🧙🧙
+🧙🧙🧙🧙
+*/
+pub static ref TEEWEE_BLOCK: BlockTypeProto =
+	vec![false, true, false, false,
+	 true, true, true, false,
+	 false, false, false, false,
+	 false, false, false, false];
+
+pub static ref ZERO_BLOCK: BlockType =
+	BlockType::from_iter(BLOCK_SIZE, BLOCK_SIZE, vec![false; BLOCK_SIZE * BLOCK_SIZE]);
+
+pub static ref BLOCKS: Vec<BlockTypeProto> =
+	vec![SMASHBOY_BLOCK.to_vec(), ORANGE_RICKY_BLOCK.to_vec(),
+		BLUE_RICKY_BLOCK.to_vec(), CLEVELAND_Z_BLOCK.to_vec(),
+		RHODE_ISLAND_Z_BLOCK.to_vec(), HERO_BLOCK.to_vec(), TEEWEE_BLOCK.to_vec()];
+}
+
+// protos are drawn row by row, blocks are indexed as (x, y)
+pub fn block_from_proto(proto: &[bool]) -> BlockType {
+	let mut block = ZERO_BLOCK.clone();
+	for y in 0..BLOCK_SIZE {
+		for x in 0..BLOCK_SIZE {
+			block.set(x, y, proto[y * BLOCK_SIZE + x]);
+		}
+	}
+
+	block
+}
+
+pub fn block_from_cells(cells: &[(usize, usize)]) -> BlockType {
+	let mut block = ZERO_BLOCK.clone();
+	for &(x, y) in cells {
+		block.set(x, y, true);
+	}
+
+	block
+}
+
+// rotate clockwise a quarter turn around the centre of the piece's box
+pub fn rotate_block(block: &BlockType, box_size: usize) -> BlockType {
+	let mut rotated = ZERO_BLOCK.clone();
+	for x in 0..box_size {
+		for y in 0..box_size {
+			rotated.set(box_size-1-y, x, *block.get(x, y).unwrap());
+		}
+	}
+
+	rotated
+}
+
+// cells of a block in one of the four orientation states (0, R, 2, L)
+pub fn block_cells(kind: BlockKind, rotation: usize) -> BlockType {
+	let mut block = block_from_proto(&BLOCKS[kind as usize]);
+	if kind == BlockKind::Smashboy {
+		return block;
+	}
+
+	for _ in 0..rotation % 4 {
+		block = rotate_block(&block, kind.box_size());
+	}
+
+	block
+}
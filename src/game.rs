@@ -0,0 +1,1102 @@
+use simple_matrix::Matrix;
+use lazy_static::lazy_static;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::blocks::*;
+use crate::gravity::*;
+use crate::mode::*;
+use crate::randomizer::*;
+use crate::rotation::*;
+use crate::scoring::*;
+use crate::settings::*;
+
+
+pub const STAGE_WIDTH: usize = 10;
+pub const STAGE_HEIGHT: usize = 20;
+pub const FRAME_RATE: f64 = 60.0;
+pub const FRAME_TIME: f64 = 1.0 / FRAME_RATE;
+
+pub const MAX_LOCK_RESETS: u32 = 15;
+pub const BIG_SCALE: i32 = 2; // stage cells along each side of a block cell in big mode
+pub const FADE_DELAY: u64 = 180; // frames a locked cell shows in full in a fading stack
+pub const FADE_FRAMES: u64 = 60; // frames it takes to fade out
+
+// what fills a stage cell; locked blocks remember their kind
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cell {
+	Empty,
+	Block(BlockKind),
+	Garbage
+}
+
+impl Cell {
+	pub fn is_filled(self) -> bool {
+		self != Cell::Empty
+	}
+
+	// one character per cell, see stage_to_string
+	pub fn to_char(self) -> char {
+		match self {
+			Cell::Empty => '.',
+			Cell::Block(kind) => kind.letter(),
+			Cell::Garbage => 'G'
+		}
+	}
+
+	pub fn from_char(c: char) -> Option<Cell> {
+		match c {
+			'.' => Some(Cell::Empty),
+			'G' => Some(Cell::Garbage),
+			_ => BLOCK_KINDS.iter()
+				.find(|kind| kind.letter() == c)
+				.map(|&kind| Cell::Block(kind))
+		}
+	}
+}
+
+pub type StageType = Matrix<Cell>;
+
+
+lazy_static! {
+static ref ZERO_STAGE: StageType=
+	StageType::from_iter(STAGE_WIDTH, STAGE_HEIGHT, vec![Cell::Empty; STAGE_HEIGHT * STAGE_WIDTH]);
+static ref ZERO_FRAMES: Matrix<u64>=
+	Matrix::from_iter(STAGE_WIDTH, STAGE_HEIGHT, vec![0; STAGE_HEIGHT * STAGE_WIDTH]);
+}
+
+// top left corner of the block's box, may lie outside the stage
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pos {
+	pub x: i32,
+	pub y: i32
+}
+
+pub const DEFAULT_START_POS: Pos = Pos{x: 3, y: 0};
+
+
+pub enum State {
+	Running,
+	LevelDone,
+	GameOver,
+	Finished, // the mode's goal was reached
+	Pause
+}
+
+// buttons held during a frame; a button pressed and released between two
+// frames still counts as held for the next one
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Inputs {
+	pub left: bool,
+	pub right: bool,
+	pub down: bool,
+	pub rotate_cw: bool,
+	pub rotate_ccw: bool,
+	pub hold: bool,
+	pub hard_drop: bool,
+	pub sonic_drop: bool,
+}
+
+impl Inputs {
+	// buttons held now that weren't held on the previous frame
+	pub fn pressed_since(self, previous: Inputs) -> Inputs {
+		Inputs {
+			left: self.left && !previous.left,
+			right: self.right && !previous.right,
+			down: self.down && !previous.down,
+			rotate_cw: self.rotate_cw && !previous.rotate_cw,
+			rotate_ccw: self.rotate_ccw && !previous.rotate_ccw,
+			hold: self.hold && !previous.hold,
+			hard_drop: self.hard_drop && !previous.hard_drop,
+			sonic_drop: self.sonic_drop && !previous.sonic_drop
+		}
+	}
+}
+
+// what happened during a single frame, used by frontends for sound and effects
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+	Moved,
+	Rotated,
+	Held,
+	Locked,
+	LinesCleared(i64),
+	Scored(Action),
+}
+
+pub struct Game {
+	pub stage: StageType,
+	pub filled_at: Matrix<u64>, // frame each stage cell was filled on
+	pub current_block: BlockType,
+	pub current_kind: BlockKind,
+	pub current_rotation: usize,
+	pub next_block: BlockType,
+	pub next_kind: BlockKind,
+	pub hold_block: BlockType,
+	pub hold_kind: Option<BlockKind>,
+	pub hold_used: bool, // only one hold until the block locks
+	pub current_position: Pos,
+	pub score: i64,
+	pub high_score: i64,
+	pub level: i64,
+	pub lines: i64,
+	pub level_lines: i64, // lines that bring the next level
+	pub state: State,
+	pub frame: u64,
+	pub pieces: u64, // blocks locked
+	pub splits: Vec<u64>, // frames at every SPLIT_LINES lines of a sprint, and at the finish
+	pub garbage_hole: Option<usize>, // column of the last garbage row's hole
+	pub garbage_risen: u32, // rows pushed up in survival
+	pub next_rise: u64, // frame the next survival row comes up
+	pub gravity: f64, // cells per frame
+	pub gravity_counter: f64,
+	pub lock_delay: u32, // frames on the ground before a block locks
+	pub lock_timer: u32, // frames spent on the ground
+	pub are: u32, // frames before the next block comes in
+	pub line_clear_delay: u32, // more frames before it after a clear
+	pub entry_timer: u32, // frames left before the next block comes in
+	pub lock_resets: u32,
+	pub lowest_row: i32, // lowest row reached by the current block
+	pub last_kick: Option<Kick>, // kick of the last rotation, None once the block moves
+	pub combo: i64, // clears in a row minus one, -1 after a block clears nothing
+	pub back_to_back: bool, // the last clear was a tetris or a T-spin
+	pub last_action: Option<Action>, // what the last locked block scored
+	pub last_inputs: Inputs,
+	pub shift_direction: i32, // -1 left, 1 right, 0 none
+	pub shift_timer: u32, // frames the shift button has been held
+	pub settings: Settings,
+	pub rotation_system: Box<dyn RotationSystem>,
+	pub scoring: Box<dyn ScoringRules>,
+	pub gravity_curve: Box<dyn GravityCurve>,
+	pub randomizer: Box<dyn Randomizer>,
+	pub seed: u64,
+	pub rng: ChaCha8Rng,
+}
+
+impl Default for Game {
+	fn default() -> Game {
+		Game::new()
+	}
+}
+
+impl Game {
+	pub fn new() -> Game {
+		Game::with_settings(Settings::default())
+	}
+
+	pub fn with_settings(settings: Settings) -> Game {
+		// short seeds are easier to share
+		let seed = settings.seed.unwrap_or_else(|| rand::thread_rng().gen::<u32>() as u64);
+		let gravity_curve = settings.gravity.create();
+		let level = settings.start_level.max(1) as i64;
+
+		Game {
+			stage: ZERO_STAGE.clone(),
+			filled_at: ZERO_FRAMES.clone(),
+			current_block: ZERO_BLOCK.clone(),
+			current_kind: BlockKind::Smashboy,
+			current_rotation: 0,
+			next_block: ZERO_BLOCK.clone(),
+			next_kind: BlockKind::Smashboy,
+			hold_block: ZERO_BLOCK.clone(),
+			hold_kind: None,
+			hold_used: false,
+			current_position: Pos{x: 0, y: 0},
+			score: 0,
+			high_score: 0,
+			level,
+			lines: 0,
+			level_lines: first_level_lines(level, settings.lines_per_level as i64),
+			state: State::Running,
+			frame: 0,
+			pieces: 0,
+			splits: Vec::new(),
+			garbage_hole: None,
+			garbage_risen: 0,
+			next_rise: 0,
+			gravity: gravity_curve.gravity(level),
+			gravity_counter: 0.0,
+			lock_delay: ms_to_frames(settings.lock_delay),
+			lock_timer: 0,
+			are: 0,
+			line_clear_delay: 0,
+			entry_timer: 0,
+			lock_resets: 0,
+			lowest_row: 0,
+			last_kick: None,
+			combo: -1,
+			back_to_back: false,
+			last_action: None,
+			last_inputs: Inputs::default(),
+			shift_direction: 0,
+			shift_timer: 0,
+			settings,
+			rotation_system: settings.rotation.create(),
+			scoring: settings.scoring.create(),
+			gravity_curve,
+			randomizer: settings.randomizer.create(),
+			seed,
+			rng: ChaCha8Rng::seed_from_u64(seed)
+		}
+	}
+
+	pub fn get_stage(&self, x: usize, y: usize) -> Cell {
+		*self.stage.get(x, y).unwrap()
+	}
+
+	pub fn is_filled(&self, x: usize, y: usize) -> bool {
+		self.get_stage(x, y).is_filled()
+	}
+
+	pub fn get_current_block(&self, x: usize, y: usize) -> bool {
+		*self.current_block.get(x, y).unwrap()
+	}
+
+	pub fn get_next_block(&self, x: usize, y: usize) -> bool {
+		*self.next_block.get(x, y).unwrap()
+	}
+
+	pub fn get_hold_block(&self, x: usize, y: usize) -> bool {
+		*self.hold_block.get(x, y).unwrap()
+	}
+
+	pub fn set_stage(&mut self, x: usize, y: usize, cell: Cell) {
+		self.stage.set(x, y, cell);
+	}
+
+	pub fn get_filled_at(&self, x: usize, y: usize) -> u64 {
+		*self.filled_at.get(x, y).unwrap()
+	}
+
+	// stage cells along each side of a block cell
+	pub fn cell_scale(&self) -> i32 {
+		if self.settings.big { BIG_SCALE } else { 1 }
+	}
+
+	pub fn inc_score(&mut self, val: i64) {
+		self.score += val;
+	}
+
+	// advance the simulation by one frame of 1 / FRAME_RATE seconds
+	pub fn step(&mut self, inputs: Inputs) -> Vec<GameEvent> {
+		let mut events = Vec::new();
+
+		match self.state {
+			State::Pause | State::GameOver | State::Finished => return events,
+			State::LevelDone => level_up(self),
+			State::Running => {}
+		}
+
+		self.frame += 1;
+		update_mode_timer(self);
+		if let State::Finished | State::GameOver = self.state {
+			return events;
+		}
+
+		let pressed = inputs.pressed_since(self.last_inputs);
+		self.last_inputs = inputs;
+
+		// the next block waits out the entry delay, a held direction charges
+		// DAS meanwhile as in TGM, other buttons pressed are dropped
+		if self.entry_timer > 0 {
+			self.entry_timer -= 1;
+			self.charge_shift(inputs, pressed);
+			return events;
+		}
+
+		if pressed.hold && hold(self) {
+			events.push(GameEvent::Held);
+		}
+
+		if pressed.rotate_cw && rotate(self, RotateDirection::Clockwise) {
+			self.reset_lock_delay();
+			events.push(GameEvent::Rotated);
+		}
+
+		if pressed.rotate_ccw && rotate(self, RotateDirection::CounterClockwise) {
+			self.reset_lock_delay();
+			events.push(GameEvent::Rotated);
+		}
+
+		self.shift(inputs, pressed, &mut events);
+
+		if pressed.down && move_down(self) {
+			self.inc_score(self.scoring.soft_drop_points());
+		}
+
+		if pressed.sonic_drop {
+			let cells = sonic_drop(self);
+			self.inc_score(cells * self.scoring.soft_drop_points());
+		}
+
+		if pressed.hard_drop {
+			let cells = sonic_drop(self);
+			self.inc_score(cells * self.scoring.hard_drop_points());
+			self.lock(&mut events);
+			return events;
+		}
+
+		// holding down speeds gravity up by the soft drop factor
+		self.gravity_counter += if inputs.down {
+			self.gravity * self.settings.soft_drop_factor as f64
+		} else {
+			self.gravity
+		};
+
+		while self.gravity_counter >= 1.0 {
+			self.gravity_counter -= 1.0;
+
+			if !move_down(self) {
+				self.gravity_counter = 0.0;
+				break;
+			}
+
+			if inputs.down {
+				self.inc_score(self.scoring.soft_drop_points());
+			}
+		}
+
+		self.update_lock_delay(&mut events);
+
+		events
+	}
+
+	// the timer only runs while the block rests on something, reaching a
+	// new lowest row starts it over
+	fn update_lock_delay(&mut self, events: &mut Vec<GameEvent>) {
+		if self.current_position.y > self.lowest_row {
+			self.lowest_row = self.current_position.y;
+			self.lock_timer = 0;
+			self.lock_resets = 0;
+		}
+
+		if can_move_down(self) {
+			return;
+		}
+
+		self.lock_timer += 1;
+		if self.lock_timer >= self.lock_delay {
+			self.lock(events);
+		}
+	}
+
+	// the direction held last wins, its timer starts over on every change
+	fn update_shift_direction(&mut self, inputs: Inputs, pressed: Inputs) {
+		if pressed.left || pressed.right {
+			self.shift_direction = if pressed.left { -1 } else { 1 };
+			self.shift_timer = 0;
+		} else if (self.shift_direction < 0 && !inputs.left)
+			|| (self.shift_direction > 0 && !inputs.right) {
+			// released, carry on with the other button if it's still held
+			self.shift_direction = if inputs.left { -1 } else if inputs.right { 1 } else { 0 };
+			self.shift_timer = 0;
+		}
+	}
+
+	// without a block to move the timer stops a frame short of DAS, so a
+	// charged block shifts on the frame it appears
+	fn charge_shift(&mut self, inputs: Inputs, pressed: Inputs) {
+		self.update_shift_direction(inputs, pressed);
+		if self.shift_direction != 0 {
+			let das = ms_to_frames(self.settings.das);
+			self.shift_timer = (self.shift_timer + 1).min(das.saturating_sub(1));
+		}
+	}
+
+	// delayed auto shift: one step on press, then after the DAS delay one
+	// step every ARR (straight to the wall when ARR is 0)
+	fn shift(&mut self, inputs: Inputs, pressed: Inputs, events: &mut Vec<GameEvent>) {
+		self.update_shift_direction(inputs, pressed);
+		if self.shift_direction == 0 {
+			return;
+		}
+
+		let das = ms_to_frames(self.settings.das);
+		let arr = ms_to_frames(self.settings.arr);
+		let steps = if pressed.left || pressed.right {
+			1
+		} else {
+			self.shift_timer += 1;
+			if self.shift_timer < das {
+				0
+			} else if arr == 0 {
+				STAGE_WIDTH
+			} else if self.shift_timer == das {
+				1
+			} else if self.shift_timer >= das + arr {
+				self.shift_timer = das;
+				1
+			} else {
+				0
+			}
+		};
+
+		for _ in 0..steps {
+			if !move_horizontal(self, self.shift_direction) {
+				break;
+			}
+
+			self.reset_lock_delay();
+			events.push(GameEvent::Moved);
+		}
+	}
+
+	// moving or rotating on the ground buys time, a limited number of times
+	fn reset_lock_delay(&mut self) {
+		if self.settings.lock_reset == LockReset::Move
+			&& self.lock_timer > 0 && self.lock_resets < MAX_LOCK_RESETS {
+			self.lock_timer = 0;
+			self.lock_resets += 1;
+		}
+	}
+
+	fn lock(&mut self, events: &mut Vec<GameEvent>) {
+		self.gravity_counter = 0.0;
+
+		let lines = lock_block(self);
+		events.push(GameEvent::Locked);
+		self.entry_timer = self.are;
+		if lines > 0 {
+			self.entry_timer += self.line_clear_delay;
+			events.push(GameEvent::LinesCleared(lines));
+		}
+
+		if let Some(action) = self.last_action {
+			events.push(GameEvent::Scored(action));
+		}
+	}
+}
+
+pub fn ms_to_frames(ms: u32) -> u32 {
+	(ms as f64 * FRAME_RATE / 1000.0).round() as u32
+}
+
+// the NES counts levels from 0, the engine from 1
+pub fn nes_level(level: i64) -> i64 {
+	level.max(1) - 1
+}
+
+// lines that bring the first level-up, as on the NES: starting high doesn't
+// mean levelling up on the first clear, but the wait is capped
+pub fn first_level_lines(start_level: i64, lines_per_level: i64) -> i64 {
+	let nes = nes_level(start_level);
+
+	((nes + 1) * lines_per_level).min((10 * lines_per_level).max((nes - 5) * lines_per_level))
+}
+
+// false for walls, the floor and filled cells; rows above the stage are blocked
+pub fn cell_free(game_state: &Game, pos: Pos) -> bool {
+	if pos.x < 0 || pos.y < 0
+		|| pos.x >= STAGE_WIDTH as i32 || pos.y >= STAGE_HEIGHT as i32 {
+		return false;
+	}
+
+	!game_state.is_filled(pos.x as usize, pos.y as usize)
+}
+
+// whether the stage cells under cell (x, y) of a block at pos are all free;
+// in big mode a block cell covers cell_scale() squared of them
+pub fn block_cell_free(game_state: &Game, pos: Pos, x: i32, y: i32) -> bool {
+	let scale = game_state.cell_scale();
+	(0..scale).all(|dx| (0..scale).all(|dy| {
+		cell_free(game_state, Pos{x: pos.x + x * scale + dx, y: pos.y + y * scale + dy})
+	}))
+}
+
+// true when every cell of the block is inside the stage and on an empty cell
+pub fn block_fits(game_state: &Game, block: &BlockType, pos: Pos) -> bool {
+	for x in 0..BLOCK_SIZE {
+		for y in 0..BLOCK_SIZE {
+			if *block.get(x, y).unwrap() && !block_cell_free(game_state, pos, x as i32, y as i32) {
+				return false;
+			}
+		}
+	}
+
+	true
+}
+
+fn shifted(pos: Pos, dx: i32, dy: i32) -> Pos {
+	Pos{x: pos.x + dx, y: pos.y + dy}
+}
+
+pub fn can_move_down(game_state: &Game) -> bool {
+	block_fits(game_state, &game_state.current_block,
+		shifted(game_state.current_position, 0, 1))
+}
+
+pub fn advance_block(game_state: &mut Game) {
+	game_state.current_position.y += 1;
+	game_state.last_kick = None;
+}
+
+pub fn move_down(game_state: &mut Game) -> bool {
+	if !can_move_down(game_state) {
+		return false;
+	}
+
+	advance_block(game_state);
+	true
+}
+
+// where the current block would land if dropped straight down, also
+// where the ghost block is drawn
+pub fn drop_position(game_state: &Game) -> Pos {
+	let mut pos = game_state.current_position;
+	while block_fits(game_state, &game_state.current_block, shifted(pos, 0, 1)) {
+		pos.y += 1;
+	}
+
+	pos
+}
+
+// drop to the floor without locking; returns the number of cells fallen
+pub fn sonic_drop(game_state: &mut Game) -> i64 {
+	let pos = drop_position(game_state);
+	let cells = (pos.y - game_state.current_position.y) as i64;
+	game_state.current_position = pos;
+	if cells > 0 {
+		game_state.last_kick = None;
+	}
+
+	cells
+}
+
+pub fn apply_block_to_stage(game_state: &mut Game) {
+	let pos = game_state.current_position;
+	let scale = game_state.cell_scale();
+	for x in 0..BLOCK_SIZE {
+		for y in 0..BLOCK_SIZE {
+			if !game_state.get_current_block(x, y) {
+				continue;
+			}
+
+			for dx in 0..scale {
+				for dy in 0..scale {
+					let stage_x = (pos.x + x as i32 * scale + dx) as usize;
+					let stage_y = (pos.y + y as i32 * scale + dy) as usize;
+					game_state.set_stage(stage_x, stage_y, Cell::Block(game_state.current_kind));
+					game_state.filled_at.set(stage_x, stage_y, game_state.frame);
+				}
+			}
+		}
+	}
+}
+
+pub fn is_full_row(game_state: &Game, row: usize) -> bool {
+	for x in 0..STAGE_WIDTH {
+		if !game_state.is_filled(x,row) {
+			return false;
+		}
+	}
+
+	true
+}
+
+pub fn remove_row(game_state: &mut Game, row: usize) {
+	for x in 0..STAGE_WIDTH {
+		game_state.set_stage(x, row, Cell::Empty);
+	}
+}
+
+pub fn copy_line(game_state: &mut Game, src: usize, dst: usize) {
+	for x in 0..STAGE_WIDTH {
+		game_state.set_stage(x, dst, game_state.get_stage(x, src));
+		game_state.filled_at.set(x, dst, game_state.get_filled_at(x, src));
+	}
+}
+
+// move every row above row down one, the top row comes in empty
+pub fn collapse_above(game_state: &mut Game, row: usize) {
+	for y in (1..=row).rev() {
+		copy_line(game_state, y-1, y);
+	}
+
+	remove_row(game_state, 0);
+}
+
+// push the stage up one row and put row in at the bottom, the falling block
+// goes up with it if it's in the way; false when filled cells were pushed
+// out the top
+pub fn insert_row(game_state: &mut Game, row: &[Cell]) -> bool {
+	let fits = (0..STAGE_WIDTH).all(|x| !game_state.is_filled(x, 0));
+	for y in 0..STAGE_HEIGHT-1 {
+		copy_line(game_state, y+1, y);
+	}
+
+	for (x, &cell) in row.iter().enumerate().take(STAGE_WIDTH) {
+		game_state.set_stage(x, STAGE_HEIGHT-1, cell);
+		game_state.filled_at.set(x, STAGE_HEIGHT-1, game_state.frame);
+	}
+
+	if check_collision(game_state) {
+		game_state.current_position.y -= 1;
+		game_state.lowest_row -= 1;
+	}
+
+	fits
+}
+
+// how much of a filled stage cell shows, from 1.0 down to 0.0
+pub fn cell_visibility(game_state: &Game, x: usize, y: usize) -> f64 {
+	match game_state.settings.stack {
+		StackVisibility::Shown => 1.0,
+		StackVisibility::Invisible => 0.0,
+		StackVisibility::Fading => {
+			let age = game_state.frame.saturating_sub(game_state.get_filled_at(x, y));
+			1.0 - (age.saturating_sub(FADE_DELAY) as f64 / FADE_FRAMES as f64).min(1.0)
+		}
+	}
+}
+
+pub fn stage_empty(game_state: &Game) -> bool {
+	for x in 0..STAGE_WIDTH {
+		for y in 0..STAGE_HEIGHT {
+			if game_state.is_filled(x, y) {
+				return false;
+			}
+		}
+	}
+
+	true
+}
+
+// the stage as text, top row first, one character per cell
+pub fn stage_to_string(game_state: &Game) -> String {
+	let mut text = String::with_capacity((STAGE_WIDTH + 1) * STAGE_HEIGHT);
+	for y in 0..STAGE_HEIGHT {
+		for x in 0..STAGE_WIDTH {
+			text.push(game_state.get_stage(x, y).to_char());
+		}
+		text.push('\n');
+	}
+
+	text
+}
+
+// fill the stage from text written by stage_to_string; missing rows at the
+// top stay empty
+pub fn load_stage(game_state: &mut Game, text: &str) -> Result<(), String> {
+	let rows: Vec<&str> = text.lines().collect();
+	if rows.len() > STAGE_HEIGHT {
+		return Err(format!("too many rows: {}", rows.len()));
+	}
+
+	let mut stage = ZERO_STAGE.clone();
+	let top = STAGE_HEIGHT - rows.len();
+	for (y, row) in rows.iter().enumerate() {
+		if row.chars().count() != STAGE_WIDTH {
+			return Err(format!("row {} is not {} cells wide", y, STAGE_WIDTH));
+		}
+
+		for (x, c) in row.chars().enumerate() {
+			let cell = Cell::from_char(c).ok_or(format!("unknown cell: {}", c))?;
+			stage.set(x, top + y, cell);
+		}
+	}
+
+	game_state.stage = stage;
+	game_state.filled_at = ZERO_FRAMES.clone();
+	Ok(())
+}
+
+// stage text for load_stage from rows listed bottom up, empty above them
+#[cfg(test)]
+pub(crate) fn stage_rows(rows: &[&str]) -> String {
+	let mut text = "..........\n".repeat(STAGE_HEIGHT - rows.len());
+	for row in rows.iter().rev() {
+		text.push_str(row);
+		text.push('\n');
+	}
+
+	text
+}
+
+pub fn remove_full_rows(game_state: &mut Game) -> i64 {
+	let mut lines = 0;
+	for y in 0..STAGE_HEIGHT {
+		while is_full_row(game_state, STAGE_HEIGHT-1-y) {
+			remove_row(game_state, STAGE_HEIGHT-1-y);
+			collapse_above(game_state, STAGE_HEIGHT-1-y);
+			lines += 1;
+		}
+	}
+
+	game_state.lines += lines;
+
+	lines
+}
+
+fn spawn_block(game_state: &mut Game, kind: BlockKind) {
+	game_state.current_kind = kind;
+	game_state.current_rotation = 0;
+	game_state.current_block = game_state.rotation_system.block(kind, 0);
+	// big blocks keep to even columns, as on a stage half as wide
+	let spawn = game_state.rotation_system.spawn_position(kind);
+	let scale = game_state.cell_scale();
+	game_state.current_position = Pos{x: spawn.x / scale * scale, y: spawn.y * scale};
+	game_state.lock_timer = 0;
+	game_state.lock_resets = 0;
+	game_state.lowest_row = game_state.current_position.y;
+	game_state.last_kick = None;
+
+	// at 20G blocks come in straight onto the stack
+	if game_state.gravity >= TWENTY_G && !check_collision(game_state) {
+		sonic_drop(game_state);
+	}
+}
+
+pub fn generate_new_block(game_state: &mut Game) {
+	let kind = game_state.next_kind;
+	spawn_block(game_state, kind);
+
+	game_state.next_kind = game_state.randomizer.next(&mut game_state.rng);
+	game_state.next_block = game_state.rotation_system.block(game_state.next_kind, 0);
+}
+
+// the first call only fills the next block, the second one spawns it
+pub fn start_game(game_state: &mut Game) {
+	start_mode(game_state);
+	generate_new_block(game_state);
+	generate_new_block(game_state);
+}
+
+pub fn check_collision(game_state: &Game) -> bool {
+	!block_fits(game_state, &game_state.current_block, game_state.current_position)
+}
+
+// dx in block cells
+fn move_horizontal(game_state: &mut Game, dx: i32) -> bool {
+	let pos = shifted(game_state.current_position, dx * game_state.cell_scale(), 0);
+	if !block_fits(game_state, &game_state.current_block, pos) {
+		return false;
+	}
+
+	game_state.current_position = pos;
+	game_state.last_kick = None;
+	true
+}
+
+pub fn move_left(game_state: &mut Game) -> bool {
+	move_horizontal(game_state, -1)
+}
+
+pub fn move_right(game_state: &mut Game) -> bool {
+	move_horizontal(game_state, 1)
+}
+
+// the rotated block and its position after the first kick that fits
+pub fn find_rotation(game_state: &Game, direction: RotateDirection)
+		-> Option<(usize, BlockType, Pos)> {
+	let system = &game_state.rotation_system;
+	let rotation = direction.apply(game_state.current_rotation);
+	let block = system.block(game_state.current_kind, rotation);
+	let scale = game_state.cell_scale();
+
+	for (dx, dy) in system.kicks(game_state, &block, direction) {
+		let pos = shifted(game_state.current_position, dx * scale, dy * scale);
+		if block_fits(game_state, &block, pos) {
+			return Some((rotation, block, pos));
+		}
+	}
+
+	None
+}
+
+pub fn can_rotate(game_state: &Game, direction: RotateDirection) -> bool {
+	find_rotation(game_state, direction).is_some()
+}
+
+pub fn rotate(game_state: &mut Game, direction: RotateDirection) -> bool {
+	match find_rotation(game_state, direction) {
+		Some((rotation, block, pos)) => {
+			let from = game_state.current_position;
+			let scale = game_state.cell_scale();
+			game_state.last_kick = Some(((pos.x - from.x) / scale, (pos.y - from.y) / scale));
+			game_state.current_rotation = rotation;
+			game_state.current_block = block;
+			game_state.current_position = pos;
+			true
+		},
+		None => false
+	}
+}
+
+// fix the current block on the stage, clear and score rows and spawn the
+// next block; returns the number of cleared rows
+pub fn lock_block(game_state: &mut Game) -> i64 {
+	let t_spin = detect_t_spin(game_state);
+	apply_block_to_stage(game_state);
+	let cleared = remove_full_rows(game_state);
+	game_state.last_action = score_lock(game_state, cleared, t_spin);
+	game_state.pieces += 1;
+
+	if game_state.lines >= game_state.level_lines {
+		game_state.state = State::LevelDone;
+	}
+
+	update_mode(game_state);
+
+	generate_new_block(game_state);
+	game_state.hold_used = false;
+	if check_collision(game_state) {
+		if let State::Finished = game_state.state {
+			return cleared;
+		}
+
+		game_state.state = State::GameOver;
+	}
+
+	cleared
+}
+
+// swap the current block with the held one, or with the next block when
+// nothing is held yet
+pub fn hold(game_state: &mut Game) -> bool {
+	if !game_state.settings.hold_enabled || game_state.hold_used {
+		return false;
+	}
+
+	let current = game_state.current_kind;
+	match game_state.hold_kind {
+		Some(kind) => spawn_block(game_state, kind),
+		None => generate_new_block(game_state)
+	}
+
+	game_state.hold_kind = Some(current);
+	game_state.hold_block = game_state.rotation_system.block(current, 0);
+	game_state.hold_used = true;
+	game_state.gravity_counter = 0.0;
+
+	if check_collision(game_state) {
+		game_state.state = State::GameOver;
+	}
+
+	true
+}
+
+// as many levels as the cleared lines are worth, a tetris can be more than one
+pub fn level_up(game_state: &mut Game) {
+	let lines_per_level = (game_state.settings.lines_per_level as i64).max(1);
+	while game_state.lines >= game_state.level_lines {
+		game_state.level += 1;
+		game_state.level_lines += lines_per_level;
+	}
+
+	game_state.gravity = game_state.gravity_curve.gravity(game_state.level);
+	game_state.state = State::Running;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn clearing_moves_the_rows_above_down() {
+		let mut game = Game::new();
+		load_stage(&mut game, &stage_rows(&["GGGGGGGGG.", "GGGGGGGGGG", "G........."])).unwrap();
+
+		assert_eq!(remove_full_rows(&mut game), 1);
+		assert_eq!(stage_to_string(&game), stage_rows(&["GGGGGGGGG.", "G........."]));
+	}
+
+	#[test]
+	fn clearing_the_top_row_empties_it() {
+		let mut game = Game::new();
+		let mut text = "GGGGGGGGGG\nG.........\n".to_string();
+		text.push_str(&"..........\n".repeat(STAGE_HEIGHT - 2));
+		load_stage(&mut game, &text).unwrap();
+
+		assert_eq!(remove_full_rows(&mut game), 1);
+		let mut expected = "..........\nG.........\n".to_string();
+		expected.push_str(&"..........\n".repeat(STAGE_HEIGHT - 2));
+		assert_eq!(stage_to_string(&game), expected);
+	}
+
+	// buttons for a frame of a made up game
+	fn script(frame: u64) -> Inputs {
+		Inputs {
+			left: frame % 50 < 8,
+			right: frame % 70 >= 60,
+			down: frame % 90 < 10,
+			rotate_cw: frame % 23 == 5,
+			rotate_ccw: frame % 31 == 7,
+			hold: frame % 97 == 11,
+			hard_drop: frame % 40 == 39,
+			sonic_drop: false
+		}
+	}
+
+	fn replay(seed: u64, frames: u64) -> Game {
+		let mut game = Game::with_settings(Settings { seed: Some(seed), ..Settings::default() });
+		start_game(&mut game);
+		for frame in 0..frames {
+			game.step(script(frame));
+		}
+
+		game
+	}
+
+	#[test]
+	fn same_seed_and_inputs_replay_the_same_game() {
+		let first = replay(42, 3000);
+		let second = replay(42, 3000);
+
+		assert!(first.pieces > 0);
+		assert_eq!(stage_to_string(&first), stage_to_string(&second));
+		assert_eq!((first.score, first.lines, first.pieces), (second.score, second.lines, second.pieces));
+	}
+
+	// the frame of every step a held right button moved a T from the spawn
+	// position
+	fn shift_frames(das: u32, arr: u32) -> Vec<u32> {
+		let mut game = Game::with_settings(Settings { das, arr, ..Settings::default() });
+		start_game(&mut game);
+		spawn_block(&mut game, BlockKind::Teewee);
+
+		let right = Inputs { right: true, ..Inputs::default() };
+		let mut moves = Vec::new();
+		for frame in 1..=30 {
+			for event in game.step(right) {
+				if event == GameEvent::Moved {
+					moves.push(frame);
+				}
+			}
+		}
+
+		moves
+	}
+
+	#[test]
+	fn das_then_arr() {
+		// 6 frames of DAS, 3 of ARR, 4 steps to the wall
+		assert_eq!(shift_frames(100, 50), vec![1, 7, 10, 13]);
+	}
+
+	#[test]
+	fn zero_arr_goes_straight_to_the_wall() {
+		assert_eq!(shift_frames(100, 0), vec![1, 7, 7, 7]);
+	}
+
+	#[test]
+	fn entry_delay_charges_das() {
+		let mut game = Game::with_settings(Settings { seed: Some(1), ..Settings::default() });
+		start_game(&mut game);
+		game.are = 20;
+		game.step(Inputs { hard_drop: true, ..Inputs::default() });
+		assert_eq!(game.entry_timer, 20);
+
+		// left goes down while the next block waits and is held from then on
+		let left = Inputs { left: true, ..Inputs::default() };
+		for _ in 0..20 {
+			assert!(game.step(left).is_empty());
+		}
+
+		assert!(game.step(left).contains(&GameEvent::Moved));
+		for _ in 0..30 {
+			game.step(left);
+		}
+
+		let pos = game.current_position;
+		assert!(!block_fits(&game, &game.current_block, Pos{x: pos.x - 1, y: pos.y}));
+	}
+
+	#[test]
+	fn lock_delay_resets_are_capped() {
+		let mut game = Game::new();
+		start_game(&mut game);
+		for _ in 0..MAX_LOCK_RESETS + 5 {
+			game.lock_timer = 5;
+			game.reset_lock_delay();
+		}
+
+		assert_eq!(game.lock_resets, MAX_LOCK_RESETS);
+		assert_eq!(game.lock_timer, 5);
+	}
+
+	#[test]
+	fn moving_on_the_ground_still_locks() {
+		let mut game = Game::new();
+		start_game(&mut game);
+		spawn_block(&mut game, BlockKind::Teewee);
+		sonic_drop(&mut game);
+
+		// tap left and right on alternate frames, a reset on every one
+		let lock_delay = game.lock_delay;
+		let mut frames = 0;
+		loop {
+			frames += 1;
+			let inputs = if frames % 2 == 0 {
+				Inputs { left: true, ..Inputs::default() }
+			} else {
+				Inputs { right: true, ..Inputs::default() }
+			};
+			if game.step(inputs).contains(&GameEvent::Locked) {
+				break;
+			}
+
+			assert!(frames <= (MAX_LOCK_RESETS + 1) * lock_delay);
+		}
+
+		assert_eq!(game.pieces, 1);
+	}
+
+	#[test]
+	fn classic_lock_reset_ignores_moves() {
+		let mut game = Game::with_settings(Settings { lock_reset: LockReset::Classic, ..Settings::default() });
+		start_game(&mut game);
+		game.lock_timer = 5;
+		game.reset_lock_delay();
+
+		assert_eq!((game.lock_timer, game.lock_resets), (5, 0));
+	}
+
+	#[test]
+	fn hold_once_per_block() {
+		let mut game = Game::with_settings(Settings { seed: Some(1), ..Settings::default() });
+		start_game(&mut game);
+		let first = game.current_kind;
+
+		assert!(hold(&mut game));
+		assert_eq!(game.hold_kind, Some(first));
+		assert!(!hold(&mut game));
+
+		lock_block(&mut game);
+		let next = game.current_kind;
+		assert!(hold(&mut game));
+		assert_eq!(game.current_kind, first);
+		assert_eq!(game.hold_kind, Some(next));
+	}
+
+	#[test]
+	fn first_level_up_waits_as_on_the_nes() {
+		// engine level n starts like NES level n - 1
+		assert_eq!(first_level_lines(1, 10), 10);
+		assert_eq!(first_level_lines(10, 10), 100);
+		assert_eq!(first_level_lines(11, 10), 100);
+		assert_eq!(first_level_lines(17, 10), 110);
+		assert_eq!(first_level_lines(19, 10), 130);
+		assert_eq!(first_level_lines(20, 10), 140);
+		assert_eq!(first_level_lines(1, 5), 5);
+	}
+
+	#[test]
+	fn one_clear_can_bring_several_levels() {
+		let mut game = Game::with_settings(Settings { lines_per_level: 1, ..Settings::default() });
+		start_game(&mut game);
+		game.lines = 4;
+		level_up(&mut game);
+
+		assert_eq!((game.level, game.level_lines), (5, 5));
+		assert_eq!(game.gravity, game.gravity_curve.gravity(5));
+	}
+
+	#[test]
+	fn hold_can_be_turned_off() {
+		let mut game = Game::with_settings(Settings { hold_enabled: false, ..Settings::default() });
+		start_game(&mut game);
+
+		assert!(!hold(&mut game));
+		assert_eq!(game.hold_kind, None);
+	}
+}
@@ -0,0 +1,82 @@
+use crate::game::*;
+use crate::randomizer::*;
+
+
+// a full row but for the hole, width columns wide from hole
+pub fn garbage_row(hole: usize, width: usize) -> Vec<Cell> {
+	(0..STAGE_WIDTH).map(|x| if (hole..hole + width).contains(&x) { Cell::Empty } else { Cell::Garbage }).collect()
+}
+
+// the hole of the next garbage row: the first one goes anywhere, then it
+// moves to another column with a chance of messiness percent; in big mode
+// holes are a block cell wide and lined up with where big blocks can go
+pub fn next_hole(game_state: &mut Game, messiness: u32) -> usize {
+	let width = game_state.cell_scale() as usize;
+	let slots = STAGE_WIDTH / width;
+	let hole = match game_state.garbage_hole {
+		None => random_index(&mut game_state.rng, slots) * width,
+		Some(hole) => {
+			if (random_index(&mut game_state.rng, 100) as u32) < messiness {
+				(hole / width + 1 + random_index(&mut game_state.rng, slots - 1)) % slots * width
+			} else {
+				hole
+			}
+		}
+	};
+
+	game_state.garbage_hole = Some(hole);
+	hole
+}
+
+// push rows of garbage in from the bottom; false when the stack was pushed
+// out the top
+pub fn add_garbage(game_state: &mut Game, rows: u32) -> bool {
+	let messiness = game_state.settings.garbage_messiness;
+	let width = game_state.cell_scale() as usize;
+	let mut fits = true;
+	for _ in 0..rows {
+		let hole = next_hole(game_state, messiness);
+		fits &= insert_row(game_state, &garbage_row(hole, width));
+	}
+
+	fits
+}
+
+// rows with garbage left in them
+pub fn garbage_rows(game_state: &Game) -> usize {
+	(0..STAGE_HEIGHT)
+		.filter(|&y| (0..STAGE_WIDTH).any(|x| game_state.get_stage(x, y) == Cell::Garbage))
+		.count()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::settings::*;
+
+	fn holes(game_state: &Game) -> Vec<usize> {
+		(0..STAGE_WIDTH).filter(|&x| game_state.get_stage(x, STAGE_HEIGHT - 1) == Cell::Empty).collect()
+	}
+
+	#[test]
+	fn one_hole_per_row() {
+		let mut game = Game::with_settings(Settings { seed: Some(7), ..Settings::default() });
+		for _ in 0..20 {
+			add_garbage(&mut game, 1);
+			assert_eq!(holes(&game).len(), 1);
+		}
+	}
+
+	#[test]
+	fn big_holes_fit_a_big_block() {
+		let settings = Settings { big: true, garbage_messiness: 100, seed: Some(7), ..Settings::default() };
+		let mut game = Game::with_settings(settings);
+		for _ in 0..20 {
+			add_garbage(&mut game, 1);
+			let row = holes(&game);
+			assert_eq!(row.len(), 2);
+			assert_eq!(row[0] % 2, 0);
+			assert_eq!(row[1], row[0] + 1);
+		}
+	}
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::game::*;
+
+
+pub const TWENTY_G: f64 = 20.0; // straight to the floor on the frame it spawns
+
+// NES frames per cell, by NES level; the last entry holds from there on
+pub const NES_FRAMES_PER_CELL: [u32; 30] = [48, 43, 38, 33, 28, 23, 18, 13, 8, 6,
+	5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1];
+
+// master levels and the G from there on, in 1/256 G as in TGM
+pub const MASTER_GRAVITY: [(i64, u32); 30] = [(0, 4), (30, 6), (35, 8), (40, 10), (50, 12),
+	(60, 16), (70, 32), (80, 48), (90, 64), (100, 80), (120, 96), (140, 112), (160, 128),
+	(170, 144), (200, 4), (220, 32), (230, 64), (233, 96), (236, 128), (239, 160),
+	(243, 192), (247, 224), (251, 256), (300, 512), (330, 768), (360, 1024), (400, 1280),
+	(420, 1024), (450, 768), (500, 5120)];
+
+// cells per frame (G) a block falls at each level, at most 20G
+pub trait GravityCurve {
+	fn name(&self) -> &str;
+
+	fn gravity(&self, level: i64) -> f64;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GravityKind {
+	Guideline,
+	Nes,
+	Custom
+}
+
+impl GravityKind {
+	// Custom starts as the guideline curve until the frontend swaps in the
+	// table it loaded
+	pub fn create(self) -> Box<dyn GravityCurve> {
+		match self {
+			GravityKind::Guideline => Box::new(GuidelineGravity),
+			GravityKind::Nes => Box::new(NesGravity),
+			GravityKind::Custom => Box::new(TableGravity::default())
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			GravityKind::Guideline => "guideline",
+			GravityKind::Nes => "nes",
+			GravityKind::Custom => "custom"
+		}
+	}
+
+	pub fn next(self) -> GravityKind {
+		match self {
+			GravityKind::Guideline => GravityKind::Nes,
+			GravityKind::Nes => GravityKind::Custom,
+			GravityKind::Custom => GravityKind::Guideline
+		}
+	}
+}
+
+impl FromStr for GravityKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<GravityKind, String> {
+		match s {
+			"guideline" => Ok(GravityKind::Guideline),
+			"nes" => Ok(GravityKind::Nes),
+			"custom" => Ok(GravityKind::Custom),
+			_ => Err(format!("unknown gravity: {}", s))
+		}
+	}
+}
+
+// seconds per row: (0.8 - (level - 1) * 0.007) ^ (level - 1)
+pub struct GuidelineGravity;
+
+impl GravityCurve for GuidelineGravity {
+	fn name(&self) -> &str {
+		"guideline"
+	}
+
+	fn gravity(&self, level: i64) -> f64 {
+		let level = level.max(1) - 1;
+		let seconds = (0.8 - level as f64 * 0.007).powi(level as i32);
+
+		(1.0 / (seconds * FRAME_RATE)).min(TWENTY_G)
+	}
+}
+
+pub struct NesGravity;
+
+impl GravityCurve for NesGravity {
+	fn name(&self) -> &str {
+		"nes"
+	}
+
+	fn gravity(&self, level: i64) -> f64 {
+		let index = (nes_level(level) as usize).min(NES_FRAMES_PER_CELL.len() - 1);
+
+		1.0 / NES_FRAMES_PER_CELL[index] as f64
+	}
+}
+
+// for the master mode's levels 0 to 999, 20G from 500 on
+pub struct MasterGravity;
+
+impl GravityCurve for MasterGravity {
+	fn name(&self) -> &str {
+		"master"
+	}
+
+	fn gravity(&self, level: i64) -> f64 {
+		let (_, gravity) = MASTER_GRAVITY.iter()
+			.rev()
+			.find(|&&(from, _)| level >= from)
+			.unwrap_or(&MASTER_GRAVITY[0]);
+
+		(*gravity as f64 / 256.0).min(TWENTY_G)
+	}
+}
+
+// G for level 1, 2 and so on; the last entry holds from there on
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableGravity {
+	pub name: String,
+	pub levels: Vec<f64>,
+}
+
+impl Default for TableGravity {
+	fn default() -> TableGravity {
+		TableGravity {
+			name: "custom".to_string(),
+			levels: (1..=20).map(|level| GuidelineGravity.gravity(level)).collect()
+		}
+	}
+}
+
+impl TableGravity {
+	// "gravity" holds the G of each level separated by spaces, see
+	// data/gravity.cfg
+	pub fn from_map(map: &HashMap<String, String>) -> Result<TableGravity, String> {
+		let levels = match map.get("gravity") {
+			Some(text) => text.split_whitespace()
+				.map(|g| g.parse::<f64>().map_err(|_| format!("bad gravity: {}", g)))
+				.collect::<Result<Vec<f64>, String>>()?,
+			None => return Err("no gravity in the table".to_string())
+		};
+
+		if levels.is_empty() || levels.iter().any(|&g| g <= 0.0) {
+			return Err("gravity needs positive values".to_string());
+		}
+
+		Ok(TableGravity {
+			name: map.get("name").cloned().unwrap_or_else(|| "custom".to_string()),
+			levels
+		})
+	}
+}
+
+impl GravityCurve for TableGravity {
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn gravity(&self, level: i64) -> f64 {
+		let index = ((level.max(1) - 1) as usize).min(self.levels.len() - 1);
+
+		self.levels[index].min(TWENTY_G)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn nes_gravity_starts_at_its_level_0() {
+		assert_eq!(NesGravity.gravity(1), 1.0 / 48.0);
+		assert_eq!(NesGravity.gravity(2), 1.0 / 43.0);
+		assert_eq!(NesGravity.gravity(30), 1.0);
+	}
+
+	#[test]
+	fn curves_start_at_level_1() {
+		assert_eq!(GuidelineGravity.gravity(1), 1.0 / FRAME_RATE);
+		assert_eq!(TableGravity::default().gravity(1), GuidelineGravity.gravity(1));
+		assert_eq!(GuidelineGravity.gravity(30), TWENTY_G);
+	}
+}
@@ -0,0 +1,22 @@
+extern crate rand;
+extern crate rand_chacha;
+
+pub mod blocks;
+pub mod game;
+pub mod garbage;
+pub mod gravity;
+pub mod mode;
+pub mod randomizer;
+pub mod rotation;
+pub mod scoring;
+pub mod settings;
+
+pub use blocks::*;
+pub use game::*;
+pub use garbage::*;
+pub use gravity::*;
+pub use mode::*;
+pub use randomizer::*;
+pub use rotation::*;
+pub use scoring::*;
+pub use settings::*;
@@ -2,464 +2,97 @@ extern crate glutin_window;
 extern crate graphics;
 extern crate opengl_graphics;
 extern crate piston;
-extern crate rand;
 extern crate find_folder;
 extern crate preferences;
+extern crate tetris;
 
+mod options;
+mod theme;
 
 use glutin_window::GlutinWindow as Window;
 use opengl_graphics::{GlGraphics, OpenGL, GlyphCache, TextureSettings};
 use piston::event_loop::{EventSettings, Events};
 use piston::input::*;
 use piston::window::WindowSettings;
-use rand::Rng;
-use simple_matrix::Matrix;
-use lazy_static::lazy_static;
 use preferences::{PreferencesMap, Preferences};
 use std::fs::File;
+use std::path::{Path, PathBuf};
 use rusty_audio::Audio;
+use tetris::*;
 
+use options::*;
+use theme::*;
 
 const HIGH_SCORE_PREF: &str = "highscore";
-
-const STAGE_WIDTH: usize = 10;
-const STAGE_HEIGHT: usize = 20;
-const UPDATE_INTERVAL: f64 = 0.5;
-const UPDATE_STEP: f64 = 0.05;
-const UPDATE_LIMIT: f64 = 0.2;
-const BLOCK_SIZE: usize = 4;
-const SCREEN_WIDTH: u32 = 400;
+const ROTATION_PREF: &str = "rotation";
+const RANDOMIZER_PREF: &str = "randomizer";
+const HOLD_PREF: &str = "hold";
+const GHOST_PREF: &str = "ghost";
+const STACK_PREF: &str = "stack";
+const BIG_PREF: &str = "big";
+const LOCK_DELAY_PREF: &str = "lock_delay";
+const LOCK_RESET_PREF: &str = "lock_reset";
+const DAS_PREF: &str = "das";
+const ARR_PREF: &str = "arr";
+const SOFT_DROP_PREF: &str = "soft_drop";
+const SCORING_PREF: &str = "scoring";
+const GRAVITY_PREF: &str = "gravity";
+const LINES_PER_LEVEL_PREF: &str = "lines_per_level";
+const START_LEVEL_PREF: &str = "start_level";
+const MODE_PREF: &str = "mode";
+const SPRINT_LINES_PREF: &str = "sprint_lines";
+const ULTRA_TIME_PREF: &str = "ultra_time";
+const MARATHON_LINES_PREF: &str = "marathon_lines";
+const MARATHON_LEVEL_PREF: &str = "marathon_level";
+const DIG_ROWS_PREF: &str = "dig_rows";
+const GARBAGE_MESSINESS_PREF: &str = "garbage_messiness";
+const RISE_INTERVAL_PREF: &str = "rise_interval";
+const THEME_PREF: &str = "theme";
+
+const SCREEN_WIDTH: u32 = 540;
 const SCREEN_HEIGHT: u32 = 500;
 const RENDER_STAGE_WIDTH: f64 = 250.0;
 const RENDER_STAGE_HEIGHT: f64 = 500.0;
-
-const BG_COLOR: [f32; 4] = [0.80, 0.85, 0.91, 1.0];
-const BG_FILL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.1];
-const GRID_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.1];
-const FILL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
-const BORDER_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
-const TEXT_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
-
-const LEVEL_UP_SCORE: i64 = 1000;
-const ROW_SCORE: i64 = 100;
-const BONUS_SCORE: i64 = 50;
-const BONUS_ALL_LINES: i64 = 500;
-
-type BlockTypeProto = Vec<bool>;
-type BlockType = Matrix<bool>;
-type StageType = Matrix<bool>;
-
-
-lazy_static! {
-/*
-🍔🍔🧙🧙
-🍔🍔🧙🧙
-🧙🧙🧙🧙
-🧙🧙🧙🧙
-*/
- static ref SMASHBOY_BLOCK: BlockTypeProto =
-	vec![true, true, false, false,
-	 true, true, false, false,
-	 false, false, false, false,
-	 false, false, false, false];
-
-
-/*
-🧙🧙🍔🧙
-🍔🍔🍔🧙
-🧙🧙🧙🧙
-🧙🧙🧙🧙
-*/
-static ref ORANGE_RICKY_BLOCK: BlockTypeProto = 
-	vec![false, false, true, false,
-	 true, true, true, false,
-	 false, false, false, false,
-	 false, false, false, false];
-
-/*
-🍔🧙🧙🧙
-🍔🍔🍔🧙
-🧙🧙🧙🧙
-🧙🧙🧙🧙
-*/
-static ref BLUE_RICKY_BLOCK: BlockTypeProto = 
-	vec![true, false, false, false,
-	 true, true, true, false,
-	 false, false, false, false,
-	 false, false, false, false];
-
-/*
-🍔🍔🧙🧙
-🧙🍔🍔🧙
-🧙🧙🧙🧙
-🧙🧙🧙🧙
-*/
-static ref CLEVELAND_Z_BLOCK: BlockTypeProto = 
-	vec![true, true, false, false,
-	 false, true, true, false,
-	 false, false, false, false,
-	 false, false, false, false];
-
-/*
-🧙🍔🍔🧙
-🍔🍔🧙🧙
-🧙🧙🧙🧙
-🧙🧙🧙🧙
-*/
-static ref RHODE_ISLAND_Z_BLOCK: BlockTypeProto = 
-	vec![false, true, true, false,
-	 true, true, false, false,
-	 false, false, false, false,
-	 false, false, false, false];
-
-/*
-🍔🍔🍔🍔
-🧙🧙🧙🧙
-🧙🧙🧙🧙
-🧙🧙🧙🧙
-*/
-static ref HERO_BLOCK: BlockTypeProto = 
-	vec![true, true, true, true,
-	false, false, false, false,
-	false, false, false, false,
-	false, false, false, false];
-
-/*
-🧙🍔🧙🧙
-🍔🍔🍔🧙
-🧙🧙🧙🧙
-🧙🧙🧙🧙
-*/
-static ref TEEWEE_BLOCK: BlockTypeProto = 
-	vec![false, true, false, false,
-	 true, true, true, false,
-	 false, false, false, false,
-	 false, false, false, false];
-
-static ref ZERO_BLOCK: BlockType =
-	BlockType::from_iter(BLOCK_SIZE, BLOCK_SIZE, vec![false; BLOCK_SIZE * BLOCK_SIZE]);
-
-static ref ZERO_STAGE: StageType=
-	StageType::from_iter(STAGE_WIDTH, STAGE_HEIGHT, vec![false; STAGE_HEIGHT * STAGE_WIDTH]);
-
-static ref BLOCKS: Vec<BlockTypeProto> =
-	vec![SMASHBOY_BLOCK.to_vec(), ORANGE_RICKY_BLOCK.to_vec(),
-		BLUE_RICKY_BLOCK.to_vec(), CLEVELAND_Z_BLOCK.to_vec(),
-		RHODE_ISLAND_Z_BLOCK.to_vec(), HERO_BLOCK.to_vec(), TEEWEE_BLOCK.to_vec()];
-}
-
-#[derive(Copy, Clone)]
-struct Pos {
-	x: usize,
-	y: usize
+const MODE_COLUMN_X: f64 = 410.0; // timers and records of the mode
+
+const MAX_START_LEVEL: u32 = 30;
+const LEADERBOARD_SIZE: usize = 10;
+const LEADERBOARD_SHOWN: usize = 5;
+// lines, last level; 0 is no cap, (0, 0) never ends
+const MARATHON_GOALS: [(u32, u32); 3] = [(150, 15), (200, 20), (0, 0)];
+const CALLOUT_FRAMES: u32 = 120; // how long a scoring callout stays up
+const OUTLINE_FRAMES: u32 = 20; // how long a hidden stack is outlined after a clear
+
+const MENU_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const MENU_TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+
+// loaded when a game starts, saved once when it finishes
+#[derive(Default)]
+struct Records {
+	best_splits: Vec<u64>, // sprint or dig personal best for the current target
+	leaderboard: Vec<i64>, // best scores first
+	saved: bool,
 }
 
-const DEFAULT_START_POS: Pos = Pos{x: 3, y: 0};
-
-
 pub struct App {
 	gl: GlGraphics, // OpenGL drawing backend.
-	duration: f64,
-	last_update: f64,
-	
-}
-
-enum State {
-	Running,
-	LevelDone,
-	GameOver,
-	Pause
-}
-
-pub struct Game {
-	stage: StageType,
-	current_block: BlockType,
-	next_block: BlockType,
-	current_position: Pos,
-	score: i64,
-	high_score: i64,
-	level: i64,
-	lines: i64,
-	state: State,
-	update_interval: f64,
-}
-
-impl Game {
-	fn new() -> Game {
-		Game {
-			stage: ZERO_STAGE.clone(),
-			current_block: ZERO_BLOCK.clone(),
-			next_block: ZERO_BLOCK.clone(),
-			current_position: Pos{x: 0, y: 0},
-			score: 0,
-			high_score: 0,
-			level: 1,
-			lines: 0,
-			state: State::Running,
-			update_interval: UPDATE_INTERVAL
-		}
-	}
-
-	fn get_stage(&self, x: usize, y: usize) -> bool {
-		*self.stage.get(x, y).unwrap()
-	}
-
-	fn get_current_block(&self, x: usize, y: usize) -> bool {
-		*self.current_block.get(x, y).unwrap()
-	}
-
-	fn get_next_block(&self, x: usize, y: usize) -> bool {
-		*self.next_block.get(x, y).unwrap()
-	}
-
-	fn set_stage(&mut self, x: usize, y: usize, val: bool) {
-		self.stage.set(x, y, val);
-	}
-
-	fn inc_score(&mut self, val: i64) {
-		self.score += val;
-	}
-}
- 
-fn can_move_down(game_state: &Game) -> bool {
-	for y in (0..BLOCK_SIZE).rev() {
-		for x in 0..BLOCK_SIZE {
-			if game_state.get_current_block(x, y) {
-
-				// hit bottom
-				if game_state.current_position.y + y == STAGE_HEIGHT-1 {
-					return false;
-				// hit block
-				} else if game_state.get_stage(
-					game_state.current_position.x + x,
-					game_state.current_position.y + y + 1) {
-					return false;
-				}
-			}
-		}
-	}
-
-	true
-}
-
-fn advance_block(game_state: &mut Game) {
-	game_state.current_position.y += 1;
-}
-
-fn apply_block_to_stage(game_state: &mut Game) {
-	for x in 0..BLOCK_SIZE {
-		for y in 0..BLOCK_SIZE {
-			if game_state.get_current_block(x, y) {
-				game_state.set_stage(
-					game_state.current_position.x + x,
-					game_state.current_position.y + y,
-					true);
-			}
-		}
-	}
-}
-
-fn is_full_row(game_state: &Game, row: usize) -> bool {
-	for x in 0..STAGE_WIDTH {
-		if !game_state.get_stage(x,row) {
-			return false;
-		}
-	}
-
-	true
-}
-
-fn remove_row(game_state: &mut Game, row: usize) {
-	for x in 0..STAGE_WIDTH {
-		game_state.set_stage(x, row, false);
-	}
-}
-
-fn copy_line(game_state: &mut Game, src: usize, dst: usize) {
-	for x in 0..STAGE_WIDTH {
-		game_state.set_stage(x, dst, game_state.get_stage(x, src));
-	}
-}
-
-fn collapse_above(mut game_state: &mut Game, row: usize) {
-	for x in 0..row-1 {
-		copy_line(&mut game_state, row-1-x, row-x);
-	}
-}
-
-fn stage_empty(game_state: &Game) -> bool {
-	for x in 0..STAGE_WIDTH {
-		for y in 0..STAGE_HEIGHT {
-			if game_state.get_stage(x, y) {
-				return false;
-			}
-		}
-	}
-
-	true
-}
-
-fn remove_full_rows(mut game_state: &mut Game) -> bool {
-	let mut lines = 0;
-	for y in 0..STAGE_HEIGHT {
-		while is_full_row(&game_state, STAGE_HEIGHT-1-y) {
-			remove_row(&mut game_state, STAGE_HEIGHT-1-y);
-			collapse_above(&mut game_state, STAGE_HEIGHT-1-y);
-			game_state.inc_score(ROW_SCORE);
-			lines += 1;
-		}
-	}
-
-	game_state.lines += lines;
-
-	if lines > 1 {
-		game_state.inc_score(BONUS_SCORE);
-	}
-
-	if stage_empty(game_state) {
-		game_state.inc_score(BONUS_ALL_LINES);
-	}
-
-	return lines > 0;
-}
-
-fn generate_new_block(game_state: &mut Game) {
-	let mut rng = rand::thread_rng();
-	let part = rng.gen_range(0, BLOCKS.len());
-	
-	game_state.current_block = game_state.next_block.clone();
-	game_state.next_block = BlockType::from_iter(BLOCK_SIZE, BLOCK_SIZE,
-		BLOCKS[part].clone());
-
-	game_state.current_position = DEFAULT_START_POS;
-}
-
-fn check_collision(game_state: &Game) -> bool {
-	for x in 0..BLOCK_SIZE {
-		for y in (0..BLOCK_SIZE).rev() {
-			if game_state.get_current_block(x, y)
-				&& game_state.get_stage(
-					game_state.current_position.x + x,
-					game_state.current_position.y + y) {
-						return true;
-			}
-		}
-	}
-
-	false
-}
-
-fn move_left(game_state: &mut Game) {
-	for x in 0..BLOCK_SIZE {
-		for y in 0..BLOCK_SIZE {
-			if game_state.get_current_block(x, y) {
-				
-				if game_state.current_position.x + x == 0 {
-					return; // hit left wall
-				} else if game_state.get_stage(
-						game_state.current_position.x + x - 1,
-						game_state.current_position.y + y) {
-					return; // hit left block
-				}
-			}
-		}
-	}
-
-	game_state.current_position.x -= 1;
-}
-
-fn move_right(game_state: &mut Game) {
-	for x in (0..BLOCK_SIZE).rev() {
-		for y in 0..BLOCK_SIZE {
-			if game_state.get_current_block(x, y) {
-				if game_state.current_position.x + x == STAGE_WIDTH-1 {
-					return; // hit right wall
-				} else if game_state.get_stage(
-					game_state.current_position.x + x + 1,
-					game_state.current_position.y + y) {
-					return; // hit right block
-				}
-			}
-		}
-	}
-
-	game_state.current_position.x += 1;
-}
-
-fn rotate_block(block: &mut BlockType) {
-	let mut tmp: BlockType = block.clone();
-
-	// move horizontal line to vertical
-	for x in  0..BLOCK_SIZE {
-		for y in 0..BLOCK_SIZE {
-			block.set(y, x, *tmp.get(x, BLOCK_SIZE-1-y).unwrap());
-		}
-	}
-
-	// shift to top left
-	tmp = block.clone();
-	let mut empty_rows = 0;
-	let mut empty_cols = 0;
-	for row in 0..BLOCK_SIZE {
-		let mut is_empty = true;
-		for col in 0..BLOCK_SIZE {
-			if *tmp.get(row, col).unwrap() {
-				is_empty = false;
-				break;
-			}
-		}
-
-		if is_empty {
-			empty_rows += 1;
-		} else {
-			break;
-		}
-	}
-
-	for col in 0..BLOCK_SIZE {
-		let mut is_empty = true;
-		for row in 0..BLOCK_SIZE {
-			if *tmp.get(row, col).unwrap() {
-				is_empty = false;
-				break;
-			}
-		}
-
-		if is_empty {
-			empty_cols += 1;
-		} else {
-			break; 
-		}
-	}
-
-	*block = ZERO_BLOCK.clone();
-	for row in  0..BLOCK_SIZE - empty_rows {
-		for col in 0..BLOCK_SIZE - empty_cols {
-			block.set(row, col, *tmp.get(row + empty_rows, col + empty_cols).unwrap());
-		}
-	}
-}
-
-fn can_rotate(game_state: &Game) -> bool {
-	let mut tmp : BlockType = game_state.current_block.clone();
-
-	rotate_block(&mut tmp);
-
-	for x in 0..BLOCK_SIZE {
-		for y in 0..BLOCK_SIZE {
-			if *tmp.get(x, y).unwrap() {
-				let x = game_state.current_position.x + x;
-				let y = game_state.current_position.y + y;
-				if x > STAGE_WIDTH-1 || y > STAGE_HEIGHT-1 {
-					return false;
-				} else if game_state.get_stage(x, y) {
-					return false;
-				}
-			}
-		}
-	}
-
-	true
+	duration: f64, // time not yet simulated
+	inputs: Inputs, // buttons for the next simulated frame
+	held: Inputs, // buttons currently held down
+	settings: Settings, // used for the next game
+	custom_scoring: TableScoring, // from data/scoring.cfg
+	custom_gravity: TableGravity, // from data/gravity.cfg
+	theme: Theme,
+	themes: Vec<PathBuf>, // theme files to switch between
+	theme_index: usize,
+	menu: OptionsMenu,
+	title: bool, // the title screen with the mode and start level pickers is up
+	title_row: usize, // 0 mode, 1 start level
+	records: Records, // of the mode being played
+	callouts: Vec<String>, // names of the last scoring action
+	callout_frames: u32, // frames left to show them
+	outline_frames: u32, // frames left to outline a hidden stack
 }
 
 impl App {
@@ -468,23 +101,59 @@ impl App {
 
 		let cell_width = RENDER_STAGE_WIDTH / (STAGE_WIDTH as f64);
 		let cell_height = RENDER_STAGE_HEIGHT / (STAGE_HEIGHT as f64);
+		let scale = game.cell_scale();
+		let block_width = cell_width * scale as f64; // a falling block's cell, bigger in big mode
+		let rules_name = format!("{} {}", self.settings.rotation.name(),
+			self.settings.randomizer.name()).to_uppercase();
+		let scoring_name = game.scoring.name().to_uppercase();
+		let theme = &self.theme;
+		let menu = &self.menu;
+		let settings = &self.settings;
+		let callouts = &self.callouts;
+		let title = self.title;
+		let title_row = self.title_row;
+		let mode_lines = mode_hud(game, &self.records);
+		// a master game is graded however it ends
+		let finished = match game.state {
+			State::Finished => true,
+			State::GameOver => game.settings.mode == GameMode::Master,
+			_ => false
+		};
+		let finish_lines = if finished {
+			vec![
+				format!("Score: {}", game.score),
+				format!("Lines: {}", game.lines),
+				format!("Level: {}", game.level),
+				format!("Time: {}", format_time(game_time(game)))]
+		} else {
+			Vec::new()
+		};
+		let finish_title = match game.settings.mode {
+			GameMode::Marathon => "YOU WIN!".to_string(),
+			GameMode::Master => format!("GRADE {}", master_grade(game)),
+			_ => "FINISHED".to_string()
+		};
+		let block_shown = game.entry_timer == 0; // hidden until it comes in
+		// a hidden stack shows again once the game is over
+		let stack_revealed = matches!(game.state, State::GameOver | State::Finished);
+		let outlined = self.outline_frames > 0;
 
 		self.gl.draw(args.viewport(), |context, gl| {
 			// clear screen
-			clear(BG_COLOR, gl);
+			clear(theme.background, gl);
 
 			// draw grid
 			for x in 0..STAGE_WIDTH {
 				for y in 0..STAGE_HEIGHT {
 					let part = rectangle::square(x as f64 * cell_width,
 						 y as f64 * cell_height, cell_width);
-					let border = Rectangle::new_border(GRID_COLOR, 1.0);
+					let border = Rectangle::new_border(theme.grid, 1.0);
 					border.draw(part, &draw_state::DrawState::default(), context.transform, gl);
 
 					let offset = cell_width / 6.0;
 					let small_part = rectangle::square(x as f64 * cell_width + offset,
 						 y as f64 * cell_height + offset, cell_width - offset*2.0);
-					rectangle(BG_FILL_COLOR, small_part, context.transform, gl);
+					rectangle(theme.background_fill, small_part, context.transform, gl);
 					
 				}
 			}
@@ -492,19 +161,23 @@ impl App {
 			// draw stage
 			for x in 0..STAGE_WIDTH {
 				for y in 0..STAGE_HEIGHT {
-					if game.get_stage(x, y) {
+					if let Some((fill, border)) = theme.cell_colors(game.get_stage(x, y)) {
+						let alpha = if stack_revealed { 1.0 } else { cell_visibility(game, x, y) as f32 };
+						let fill = faded(fill, alpha);
+						let border = if outlined { border } else { faded(border, alpha) };
+
 						// fill
 						let posx = x as f64 * cell_width;
 						let posy = y as f64 * cell_height;
 						let offset = cell_width / 6.0;
 						let part = rectangle::square(posx + offset, posy + offset,
 							 cell_width - offset * 2.0);
-						rectangle(FILL_COLOR, part, context.transform, gl);
+						rectangle(fill, part, context.transform, gl);
 
 						// border
 						let border_part = rectangle::square(x as f64 * cell_width,
 							 y as f64 * cell_height, cell_width);
-						let border = Rectangle::new_border(FILL_COLOR, 1.0);
+						let border = Rectangle::new_border(border, 1.0);
 						border.draw(border_part, &draw_state::DrawState::default(),
 						 context.transform, gl);
 					}
@@ -513,27 +186,47 @@ impl App {
 
 			let grid_border_part = rectangle::rectangle_by_corners(
 				0.0, 0.0, RENDER_STAGE_WIDTH, RENDER_STAGE_HEIGHT);
-			let border = Rectangle::new_border(FILL_COLOR, 1.0);
+			let border = Rectangle::new_border(theme.border, 1.0);
 			border.draw(grid_border_part, 
 				&draw_state::DrawState::default(),
 				context.transform,
 				gl);
 
+			// draw ghost block
+			if game.settings.ghost_enabled && block_shown {
+				let ghost = drop_position(game);
+				for x in 0..BLOCK_SIZE {
+					for y in 0..BLOCK_SIZE {
+						if game.get_current_block(x, y) {
+							let posx = (x as i32 * scale + ghost.x) as f64 * cell_width;
+							let posy = (y as i32 * scale + ghost.y) as f64 * cell_height;
+							let offset = block_width / 6.0;
+							let part = rectangle::square(posx + offset, posy + offset,
+								 block_width - offset * 2.0);
+							let border = Rectangle::new_border(theme.ghost, 1.0);
+							border.draw(part, &draw_state::DrawState::default(),
+							 context.transform, gl);
+						}
+					}
+				}
+			}
+
 			// draw current block
+			let (fill, block_border) = theme.block_colors(game.current_kind);
 			for x in 0..BLOCK_SIZE {
 				for y in 0..BLOCK_SIZE {
-					if game.get_current_block(x, y) {
+					if block_shown && game.get_current_block(x, y) {
 						// fill
-						let posx = (x + game.current_position.x) as f64 * cell_width;
-						let posy = (y + game.current_position.y) as f64 * cell_height;
-						let offset = cell_width / 6.0;
+						let posx = (x as i32 * scale + game.current_position.x) as f64 * cell_width;
+						let posy = (y as i32 * scale + game.current_position.y) as f64 * cell_height;
+						let offset = block_width / 6.0;
 						let part = rectangle::square(posx + offset, posy + offset,
-							 cell_width - offset*2.0);
-						rectangle(FILL_COLOR, part, context.transform, gl);
+							 block_width - offset*2.0);
+						rectangle(fill, part, context.transform, gl);
 
 						// border
-						let border_part = rectangle::square(posx, posy, cell_width);
-						let border = Rectangle::new_border(BORDER_COLOR, 1.0);
+						let border_part = rectangle::square(posx, posy, block_width);
+						let border = Rectangle::new_border(block_border, 1.0);
 						border.draw(border_part, &draw_state::DrawState::default(),
 						 context.transform, gl);
 
@@ -542,70 +235,71 @@ impl App {
 			}
 
 			// text
-			text::Text::new_color(TEXT_COLOR, 16)
+			text::Text::new_color(theme.text, 16)
 				.draw("Score:",
 					glyph_cache,
 					&context.draw_state,
 					context.transform.trans(300.0, 30.0),
 					gl).unwrap();
 
-			text::Text::new_color(TEXT_COLOR, 16)
+			text::Text::new_color(theme.text, 16)
 				.draw(format!("{}", game.score).as_str(),
 					glyph_cache,
 					&context.draw_state,
 					context.transform.trans(310.0, 50.0),
 					gl).unwrap();
 					
-			text::Text::new_color(TEXT_COLOR, 16)
+			text::Text::new_color(theme.text, 16)
 				.draw("Level:",
 					glyph_cache,
 					&context.draw_state,
 					context.transform.trans(300.0, 80.0),
 					gl).unwrap();
 
-			text::Text::new_color(TEXT_COLOR, 16)
+			text::Text::new_color(theme.text, 16)
 				.draw(format!("{}", game.level).as_str(),
 					glyph_cache,
 					&context.draw_state,
 					context.transform.trans(320.0, 100.0),
 					gl).unwrap();
 
-			text::Text::new_color(TEXT_COLOR, 16)
+			text::Text::new_color(theme.text, 16)
 				.draw("Lines:",
 					glyph_cache,
 					&context.draw_state,
 					context.transform.trans(300.0, 130.0),
 					gl).unwrap();
 
-			text::Text::new_color(TEXT_COLOR, 16)
+			text::Text::new_color(theme.text, 16)
 				.draw(format!("{}", game.lines).as_str(),
 					glyph_cache,
 					&context.draw_state,
 					context.transform.trans(320.0, 150.0),
 					gl).unwrap();
 
-			text::Text::new_color(TEXT_COLOR, 16)
+			text::Text::new_color(theme.text, 16)
 				.draw("Next:",
 					glyph_cache,
 					&context.draw_state,
 					context.transform.trans(300.0, 180.0),
 					gl).unwrap();
 				
-			text::Text::new_color(TEXT_COLOR, 16)
+			text::Text::new_color(theme.text, 16)
 				.draw("High score:",
 					glyph_cache,
 					&context.draw_state,
-					context.transform.trans(280.0, 430.0),
+					context.transform.trans(280.0, 420.0),
 					gl).unwrap();
 					
-			text::Text::new_color(TEXT_COLOR, 16)
+			text::Text::new_color(theme.text, 16)
 				.draw(format!("{}", game.high_score).as_str(),
 					glyph_cache,
 					&context.draw_state,
-					context.transform.trans(300.0, 450.0),
+					context.transform.trans(300.0, 440.0),
 					gl).unwrap();
 					
 			// draw next block
+			let (fill, block_border) = theme.block_colors(game.next_kind);
 			for x in 0..BLOCK_SIZE {
 				for y in 0..BLOCK_SIZE {
 					if game.get_next_block(x, y) {
@@ -615,14 +309,14 @@ impl App {
 						let offset = cell_width / 6.0;
 						let part = rectangle::square(posx + offset, posy + offset,
 							 cell_width - offset*2.0);
-						rectangle(FILL_COLOR,
+						rectangle(fill,
 							part,
 							context.transform.trans(300.0, 200.0),
 							gl);
 
 						// border
 						let border_part = rectangle::square(posx, posy, cell_width);
-						let border = Rectangle::new_border(BORDER_COLOR, 1.0);
+						let border = Rectangle::new_border(block_border, 1.0);
 						border.draw(border_part, 
 							&draw_state::DrawState::default(),
 							context.transform.trans(300.0, 200.0),
@@ -632,52 +326,669 @@ impl App {
 				}
 			}
 
+			if game.settings.hold_enabled {
+				text::Text::new_color(theme.text, 16)
+					.draw("Hold:",
+						glyph_cache,
+						&context.draw_state,
+						context.transform.trans(300.0, 280.0),
+						gl).unwrap();
+			}
+
+			// draw hold block
+			let (fill, block_border) = game.hold_kind
+				.map_or((theme.border, theme.border), |kind| theme.block_colors(kind));
+			for x in 0..BLOCK_SIZE {
+				for y in 0..BLOCK_SIZE {
+					if game.get_hold_block(x, y) {
+						// fill
+						let posx = x as f64 * cell_width;
+						let posy = y as f64 * cell_height;
+						let offset = cell_width / 6.0;
+						let part = rectangle::square(posx + offset, posy + offset,
+							 cell_width - offset*2.0);
+						rectangle(fill,
+							part,
+							context.transform.trans(300.0, 300.0),
+							gl);
+
+						// border
+						let border_part = rectangle::square(posx, posy, cell_width);
+						let border = Rectangle::new_border(block_border, 1.0);
+						border.draw(border_part, 
+							&draw_state::DrawState::default(),
+							context.transform.trans(300.0, 300.0),
+						 	gl);
+
+					}
+				}
+			}
+
+			text::Text::new_color(theme.text, 16)
+				.draw(rules_name.as_str(),
+					glyph_cache,
+					&context.draw_state,
+					context.transform.trans(280.0, 466.0),
+					gl).unwrap();
+
+			text::Text::new_color(theme.text, 16)
+				.draw(scoring_name.as_str(),
+					glyph_cache,
+					&context.draw_state,
+					context.transform.trans(280.0, 486.0),
+					gl).unwrap();
+
+			// mode column
+			for (i, line) in mode_lines.iter().enumerate() {
+				text::Text::new_color(theme.text, 14)
+					.draw(line.as_str(),
+						glyph_cache,
+						&context.draw_state,
+						context.transform.trans(MODE_COLUMN_X, 30.0 + i as f64 * 20.0),
+						gl).unwrap();
+			}
+
+			if let State::GameOver = game.state {
+				text::Text::new_color(theme.text, 16)
+					.draw(format!("Seed: {}", game.seed).as_str(),
+						glyph_cache,
+						&context.draw_state,
+						context.transform.trans(280.0, 404.0),
+						gl).unwrap();
+			}
+
 			let state_str = match game.state {
 				State::LevelDone => "LEVEL UP",
 				State::GameOver => "GAME OVER",
+				State::Finished => "FINISHED",
 				State::Pause => "PAUSE",
 				_ => ""
 			};
 
-			text::Text::new_color(TEXT_COLOR, 16)
-				.draw(format!("{}", state_str).as_str(),
+			text::Text::new_color(theme.text, 16)
+				.draw(state_str,
 					glyph_cache,
 					&context.draw_state,
-					context.transform.trans(300.0, 330.0),
+					context.transform.trans(300.0, 382.0),
 					gl).unwrap();
+
+			// scoring callouts over the stage
+			for (i, callout) in callouts.iter().enumerate() {
+				text::Text::new_color(theme.text, 16)
+					.draw(callout.as_str(),
+						glyph_cache,
+						&context.draw_state,
+						context.transform.trans(20.0, 60.0 + i as f64 * 24.0),
+						gl).unwrap();
+			}
+
+			// results of a finished mode over the stage
+			if !finish_lines.is_empty() {
+				rectangle(MENU_COLOR, [15.0, 140.0, 220.0, 200.0], context.transform, gl);
+
+				text::Text::new_color(MENU_TEXT_COLOR, 24)
+					.draw(finish_title.as_str(),
+						glyph_cache,
+						&context.draw_state,
+						context.transform.trans(30.0, 180.0),
+						gl).unwrap();
+
+				for (i, line) in finish_lines.iter().enumerate() {
+					text::Text::new_color(MENU_TEXT_COLOR, 16)
+						.draw(line.as_str(),
+							glyph_cache,
+							&context.draw_state,
+							context.transform.trans(30.0, 220.0 + i as f64 * 24.0),
+							gl).unwrap();
+				}
+			}
+
+			// title screen over the game
+			if title {
+				rectangle(MENU_COLOR,
+					[0.0, 0.0, SCREEN_WIDTH as f64, SCREEN_HEIGHT as f64],
+					context.transform, gl);
+
+				text::Text::new_color(MENU_TEXT_COLOR, 32)
+					.draw("TETRIS",
+						glyph_cache,
+						&context.draw_state,
+						context.transform.trans(40.0, 140.0),
+						gl).unwrap();
+
+				let rows = [
+					format!("Mode: < {} >", mode_name(settings).to_uppercase()),
+					format!("Start level: < {} >", settings.start_level)];
+				for (i, row) in rows.iter().enumerate() {
+					let marker = if i == title_row { ">" } else { " " };
+					text::Text::new_color(MENU_TEXT_COLOR, 16)
+						.draw(format!("{} {}", marker, row).as_str(),
+							glyph_cache,
+							&context.draw_state,
+							context.transform.trans(40.0, 220.0 + i as f64 * 30.0),
+							gl).unwrap();
+				}
+
+				text::Text::new_color(MENU_TEXT_COLOR, 12)
+					.draw("UP/DOWN select  LEFT/RIGHT change  ENTER start  O options",
+						glyph_cache,
+						&context.draw_state,
+						context.transform.trans(40.0, 450.0),
+						gl).unwrap();
+			}
+
+			// options menu over everything
+			if menu.open {
+				rectangle(MENU_COLOR,
+					[0.0, 0.0, SCREEN_WIDTH as f64, SCREEN_HEIGHT as f64],
+					context.transform, gl);
+
+				text::Text::new_color(MENU_TEXT_COLOR, 20)
+					.draw("OPTIONS",
+						glyph_cache,
+						&context.draw_state,
+						context.transform.trans(40.0, 80.0),
+						gl).unwrap();
+
+				for (i, item) in OPTION_ITEMS.iter().enumerate() {
+					let marker = if i == menu.selected { ">" } else { " " };
+					let line = format!("{} {}: {}", marker, item.label(),
+						item.value(settings, &theme.name));
+					text::Text::new_color(MENU_TEXT_COLOR, 16)
+						.draw(line.as_str(),
+							glyph_cache,
+							&context.draw_state,
+							context.transform.trans(40.0, 130.0 + i as f64 * 30.0),
+							gl).unwrap();
+				}
+
+				text::Text::new_color(MENU_TEXT_COLOR, 12)
+					.draw("UP/DOWN select  LEFT/RIGHT change  O close",
+						glyph_cache,
+						&context.draw_state,
+						context.transform.trans(40.0, 450.0),
+						gl).unwrap();
+			}
 		});
 	}
 
+	// custom scoring and gravity need the tables loaded at startup
+	fn new_game(&self) -> Game {
+		let mut game = Game::with_settings(self.settings);
+		if self.settings.scoring == ScoringKind::Custom {
+			game.scoring = Box::new(self.custom_scoring.clone());
+		}
+
+		if self.settings.gravity == GravityKind::Custom {
+			game.gravity_curve = Box::new(self.custom_gravity.clone());
+			game.gravity = game.gravity_curve.gravity(game.level);
+		}
+
+		game
+	}
+
 	fn update(&mut self, args: &UpdateArgs
-			, mut game_state: &mut Game
+			, game_state: &mut Game
 			, audio: &mut Audio
 			, sound_on: bool) {
 		self.duration += args.dt;
-		
-		if self.duration > self.last_update + game_state.update_interval {
-			self.last_update = self.duration;
-			
-			if can_move_down(&game_state ) {
-				advance_block(&mut game_state);
-			} else {
-				apply_block_to_stage(&mut game_state);
-				if remove_full_rows(&mut game_state) && sound_on {
-					audio.play("line");
+
+		while self.duration >= FRAME_TIME {
+			self.duration -= FRAME_TIME;
+
+			let events = game_state.step(self.inputs);
+			self.inputs = self.held;
+
+			if self.callout_frames > 0 {
+				self.callout_frames -= 1;
+				if self.callout_frames == 0 {
+					self.callouts.clear();
 				}
+			}
+
+			self.outline_frames = self.outline_frames.saturating_sub(1);
 
-				if game_state.score >= LEVEL_UP_SCORE * game_state.level {
-					game_state.state = State::LevelDone;
+			for event in &events {
+				match event {
+					GameEvent::Scored(action) => {
+						self.callouts = action.callouts();
+						self.callout_frames = CALLOUT_FRAMES;
+					},
+					GameEvent::LinesCleared(_) => self.outline_frames = OUTLINE_FRAMES,
+					_ => {}
 				}
+			}
+
+			if !sound_on {
+				continue;
+			}
 
-				generate_new_block(&mut game_state);
-				if check_collision(&game_state) {
-					game_state.state = State::GameOver;
+			for event in events {
+				match event {
+					GameEvent::Moved => audio.play("move"),
+					GameEvent::Rotated => audio.play("rotate"),
+					GameEvent::Held => audio.play("move"),
+					GameEvent::LinesCleared(_) => audio.play("line"),
+					_ => {}
 				}
 			}
 		}
 	}
 }
 
+// m:ss.mmm
+fn format_time(seconds: f64) -> String {
+	let ms = (seconds * 1000.0).round() as u64;
+	format!("{}:{:02}.{:03}", ms / 60000, ms / 1000 % 60, ms % 1000)
+}
+
+// the mode with its goal, e.g. "sprint 40"
+fn mode_name(settings: &Settings) -> String {
+	match settings.mode {
+		GameMode::Endless => settings.mode.name().to_string(),
+		GameMode::Sprint => format!("{} {}", settings.mode.name(), settings.sprint_lines),
+		GameMode::Ultra => format!("{} {}:{:02}", settings.mode.name(),
+			settings.ultra_time / 60, settings.ultra_time % 60),
+		GameMode::Marathon => match (settings.marathon_lines, settings.marathon_level) {
+			(0, 0) => format!("{} endless", settings.mode.name()),
+			(0, level) => format!("{} level {}", settings.mode.name(), level),
+			(lines, _) => format!("{} {}", settings.mode.name(), lines)
+		},
+		GameMode::Dig => format!("{} {}", settings.mode.name(), settings.dig_rows),
+		GameMode::Survival | GameMode::Master => settings.mode.name().to_string()
+	}
+}
+
+// lines for the mode column
+fn mode_hud(game: &Game, records: &Records) -> Vec<String> {
+	let mut lines = vec![mode_name(&game.settings).to_uppercase()];
+	match game.settings.mode {
+		GameMode::Endless => {},
+		GameMode::Sprint => {
+			lines.push(format_time(game_time(game)));
+			lines.push(format!("PPS: {:.2}", pieces_per_second(game)));
+			lines.push("Splits:".to_string());
+			for (i, &frame) in game.splits.iter().enumerate() {
+				let time = frame as f64 / FRAME_RATE;
+				let diff = match records.best_splits.get(i) {
+					Some(&best) => format!(" {:+.2}", time - best as f64 / FRAME_RATE),
+					None => String::new()
+				};
+				lines.push(format!("{}{}", format_time(time), diff));
+			}
+
+			if let Some(&best) = records.best_splits.last() {
+				lines.push(format!("Best: {}", format_time(best as f64 / FRAME_RATE)));
+			}
+		},
+		GameMode::Ultra => {
+			lines.push(format_time(time_left(game)));
+			lines.push(format!("PPS: {:.2}", pieces_per_second(game)));
+		},
+		GameMode::Marathon => {
+			lines.push(format_time(game_time(game)));
+			if game.settings.marathon_lines > 0 {
+				let left = (game.settings.marathon_lines as i64 - game.lines).max(0);
+				lines.push(format!("Lines left: {}", left));
+			}
+		},
+		GameMode::Dig => {
+			lines.push(format_time(game_time(game)));
+			lines.push(format!("Garbage left: {}", garbage_rows(game)));
+			if let Some(&best) = records.best_splits.last() {
+				lines.push(format!("Best: {}", format_time(best as f64 / FRAME_RATE)));
+			}
+		},
+		GameMode::Survival => {
+			lines.push(format_time(game_time(game)));
+			let next = game.next_rise.saturating_sub(game.frame) as f64 / FRAME_RATE;
+			lines.push(format!("Next row: {:.1}", next));
+			lines.push(format!("Rows risen: {}", game.garbage_risen));
+		},
+		GameMode::Master => {
+			lines.push(format_time(game_time(game)));
+			lines.push(format!("Grade: {}", master_grade(game)));
+			lines.push("Sections:".to_string());
+			let mut start = 0;
+			for &frame in game.splits.iter() {
+				lines.push(format_time((frame - start) as f64 / FRAME_RATE));
+				start = frame;
+			}
+		}
+	}
+
+	if !records.leaderboard.is_empty() {
+		lines.push("Top:".to_string());
+		for (i, score) in records.leaderboard.iter().take(LEADERBOARD_SHOWN).enumerate() {
+			lines.push(format!("{}. {}", i + 1, score));
+		}
+	}
+
+	lines
+}
+
+// the title screen offers every marathon goal before moving on
+fn next_mode(settings: &mut Settings) {
+	if settings.mode == GameMode::Marathon {
+		let goal = (settings.marathon_lines, settings.marathon_level);
+		let next = MARATHON_GOALS.iter().position(|&g| g == goal).map(|i| i + 1);
+		if let Some(&(lines, level)) = next.and_then(|i| MARATHON_GOALS.get(i)) {
+			settings.marathon_lines = lines;
+			settings.marathon_level = level;
+			return;
+		}
+	}
+
+	settings.mode = settings.mode.next();
+	if settings.mode == GameMode::Marathon {
+		settings.marathon_lines = MARATHON_GOALS[0].0;
+		settings.marathon_level = MARATHON_GOALS[0].1;
+	}
+}
+
+// the endless mode keeps the old key so existing high scores carry over
+fn high_score_pref(settings: &Settings) -> String {
+	match settings.mode {
+		GameMode::Endless => HIGH_SCORE_PREF.to_string(),
+		GameMode::Marathon => format!("{}_marathon_{}", HIGH_SCORE_PREF, settings.marathon_lines),
+		_ => format!("{}_{}", HIGH_SCORE_PREF, settings.mode.name())
+	}
+}
+
+// modes racing the clock
+fn best_time_pref(settings: &Settings) -> Option<String> {
+	match settings.mode {
+		GameMode::Sprint => Some(format!("sprint_{}_splits", settings.sprint_lines)),
+		GameMode::Dig => Some(format!("dig_{}_{}_splits", settings.dig_rows,
+			settings.garbage_messiness)),
+		_ => None
+	}
+}
+
+// modes with a table of best scores
+fn leaderboard_pref(settings: &Settings) -> Option<String> {
+	match settings.mode {
+		GameMode::Endless => Some("leaderboard_endless".to_string()),
+		GameMode::Ultra => Some(format!("leaderboard_ultra_{}", settings.ultra_time)),
+		GameMode::Marathon => Some(format!("leaderboard_marathon_{}", settings.marathon_lines)),
+		GameMode::Survival => Some("leaderboard_survival".to_string()),
+		GameMode::Master => Some("leaderboard_master".to_string()),
+		GameMode::Sprint | GameMode::Dig => None
+	}
+}
+
+// numbers separated by spaces
+fn load_list<T: std::str::FromStr>(prefs: &PreferencesMap<String>, key: &str) -> Vec<T> {
+	prefs.get(key)
+		.map(|list| list.split_whitespace().filter_map(|n| n.parse().ok()).collect())
+		.unwrap_or_default()
+}
+
+fn save_list<T: ToString>(prefs: &mut PreferencesMap<String>, key: String, list: &[T]) {
+	let list: Vec<String> = list.iter().map(|n| n.to_string()).collect();
+	prefs.insert(key, list.join(" "));
+}
+
+fn load_records(prefs: &PreferencesMap<String>, settings: &Settings) -> Records {
+	Records {
+		best_splits: best_time_pref(settings)
+			.map_or(Vec::new(), |key| load_list(prefs, &key)),
+		leaderboard: leaderboard_pref(settings)
+			.map_or(Vec::new(), |key| load_list(prefs, &key)),
+		saved: false
+	}
+}
+
+// a finished race beating the best time, a score making the leaderboard
+fn save_records(prefs: &mut PreferencesMap<String>, records: &mut Records, game: &Game) {
+	records.saved = true;
+
+	let finished = matches!(game.state, State::Finished);
+	if let (Some(key), true) = (best_time_pref(&game.settings), finished) {
+		let new_best = match records.best_splits.last() {
+			Some(&best) => game.frame < best,
+			None => true
+		};
+
+		if new_best {
+			records.best_splits = game.splits.clone();
+			save_list(prefs, key, &records.best_splits);
+		}
+	}
+
+	if let Some(key) = leaderboard_pref(&game.settings) {
+		records.leaderboard.push(game.score);
+		records.leaderboard.sort_by(|a, b| b.cmp(a));
+		records.leaderboard.truncate(LEADERBOARD_SIZE);
+		save_list(prefs, key, &records.leaderboard);
+	}
+}
+
+fn set_input(inputs: &mut Inputs, button: Button, held: bool) {
+	match button {
+		Button::Keyboard(Key::Left) => inputs.left = held,
+		Button::Keyboard(Key::Right) => inputs.right = held,
+		Button::Keyboard(Key::Down) => inputs.down = held,
+		Button::Keyboard(Key::Up) | Button::Keyboard(Key::X)
+			| Button::Mouse(MouseButton::Left) => inputs.rotate_cw = held,
+		Button::Keyboard(Key::Z)
+			| Button::Mouse(MouseButton::Right) => inputs.rotate_ccw = held,
+		Button::Keyboard(Key::Space) => inputs.hard_drop = held,
+		Button::Keyboard(Key::A) => inputs.sonic_drop = held,
+		Button::Keyboard(Key::C) | Button::Keyboard(Key::LShift) => inputs.hold = held,
+		_ => {}
+	}
+}
+
+// command line: --seed <n> replays the same blocks every game, --level <n>
+// picks the start level
+fn parse_args(settings: &mut Settings) {
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--seed" => {
+				settings.seed = args.next().and_then(|seed| seed.parse().ok());
+				if settings.seed.is_none() {
+					eprintln!("--seed expects a number");
+				}
+			},
+			"--level" => {
+				match args.next().and_then(|level| level.parse::<u32>().ok()) {
+					Some(level) => settings.start_level = level.clamp(1, MAX_START_LEVEL),
+					None => eprintln!("--level expects a number")
+				}
+			},
+			_ => eprintln!("unknown argument: {}", arg)
+		}
+	}
+}
+
+fn load_settings(prefs: &PreferencesMap<String>) -> Settings {
+	let mut settings = Settings::default();
+
+	if let Some(rotation) = prefs.get(ROTATION_PREF) {
+		settings.rotation = rotation.parse().unwrap_or(settings.rotation);
+	}
+
+	if let Some(scoring) = prefs.get(SCORING_PREF) {
+		settings.scoring = scoring.parse().unwrap_or(settings.scoring);
+	}
+
+	if let Some(gravity) = prefs.get(GRAVITY_PREF) {
+		settings.gravity = gravity.parse().unwrap_or(settings.gravity);
+	}
+
+	if let Some(lines) = prefs.get(LINES_PER_LEVEL_PREF) {
+		settings.lines_per_level = lines.parse().unwrap_or(settings.lines_per_level).max(1);
+	}
+
+	if let Some(mode) = prefs.get(MODE_PREF) {
+		settings.mode = mode.parse().unwrap_or(settings.mode);
+	}
+
+	if let Some(lines) = prefs.get(SPRINT_LINES_PREF) {
+		settings.sprint_lines = lines.parse().unwrap_or(settings.sprint_lines).max(1);
+	}
+
+	if let Some(time) = prefs.get(ULTRA_TIME_PREF) {
+		settings.ultra_time = time.parse().unwrap_or(settings.ultra_time).max(1);
+	}
+
+	if let Some(lines) = prefs.get(MARATHON_LINES_PREF) {
+		settings.marathon_lines = lines.parse().unwrap_or(settings.marathon_lines);
+	}
+
+	if let Some(level) = prefs.get(MARATHON_LEVEL_PREF) {
+		settings.marathon_level = level.parse().unwrap_or(settings.marathon_level);
+	}
+
+	if let Some(rows) = prefs.get(DIG_ROWS_PREF) {
+		settings.dig_rows = rows.parse().unwrap_or(settings.dig_rows).clamp(1, MAX_DIG_ROWS);
+	}
+
+	if let Some(messiness) = prefs.get(GARBAGE_MESSINESS_PREF) {
+		settings.garbage_messiness = messiness.parse().unwrap_or(settings.garbage_messiness).min(100);
+	}
+
+	if let Some(interval) = prefs.get(RISE_INTERVAL_PREF) {
+		settings.rise_interval = interval.parse().unwrap_or(settings.rise_interval).max(1);
+	}
+
+	if let Some(level) = prefs.get(START_LEVEL_PREF) {
+		settings.start_level = level.parse().unwrap_or(settings.start_level)
+			.clamp(1, MAX_START_LEVEL);
+	}
+
+	if let Some(ghost) = prefs.get(GHOST_PREF) {
+		settings.ghost_enabled = ghost.parse().unwrap_or(settings.ghost_enabled);
+	}
+
+	if let Some(lock_delay) = prefs.get(LOCK_DELAY_PREF) {
+		settings.lock_delay = lock_delay.parse().unwrap_or(settings.lock_delay);
+	}
+
+	if let Some(big) = prefs.get(BIG_PREF) {
+		settings.big = big.parse().unwrap_or(settings.big);
+	}
+
+	if let Some(stack) = prefs.get(STACK_PREF) {
+		settings.stack = stack.parse().unwrap_or(settings.stack);
+	}
+
+	if let Some(lock_reset) = prefs.get(LOCK_RESET_PREF) {
+		settings.lock_reset = lock_reset.parse().unwrap_or(settings.lock_reset);
+	}
+
+	if let Some(das) = prefs.get(DAS_PREF) {
+		settings.das = das.parse().unwrap_or(settings.das);
+	}
+
+	if let Some(arr) = prefs.get(ARR_PREF) {
+		settings.arr = arr.parse().unwrap_or(settings.arr);
+	}
+
+	if let Some(soft_drop) = prefs.get(SOFT_DROP_PREF) {
+		settings.soft_drop_factor = soft_drop.parse().unwrap_or(settings.soft_drop_factor);
+	}
+
+	load_mode_rules(prefs, &mut settings);
+	settings
+}
+
+// hold and the randomizer are kept per mode, endless keeps the old keys
+fn mode_rule_pref(key: &str, mode: GameMode) -> String {
+	match mode {
+		GameMode::Endless => key.to_string(),
+		_ => format!("{}_{}", key, mode.name())
+	}
+}
+
+// the mode's defaults until the player picks others for it
+fn load_mode_rules(prefs: &PreferencesMap<String>, settings: &mut Settings) {
+	let mode = settings.mode;
+	settings.randomizer = prefs.get(&mode_rule_pref(RANDOMIZER_PREF, mode))
+		.and_then(|randomizer| randomizer.parse().ok())
+		.unwrap_or_else(|| mode.default_randomizer());
+	settings.hold_enabled = prefs.get(&mode_rule_pref(HOLD_PREF, mode))
+		.and_then(|hold| hold.parse().ok())
+		.unwrap_or_else(|| mode.default_hold());
+}
+
+fn save_settings(prefs: &mut PreferencesMap<String>, settings: &Settings) {
+	prefs.insert(ROTATION_PREF.to_string(), settings.rotation.name().to_string());
+	prefs.insert(mode_rule_pref(RANDOMIZER_PREF, settings.mode),
+		settings.randomizer.name().to_string());
+	prefs.insert(SCORING_PREF.to_string(), settings.scoring.name().to_string());
+	prefs.insert(GRAVITY_PREF.to_string(), settings.gravity.name().to_string());
+	prefs.insert(LINES_PER_LEVEL_PREF.to_string(), settings.lines_per_level.to_string());
+	prefs.insert(START_LEVEL_PREF.to_string(), settings.start_level.to_string());
+	prefs.insert(MODE_PREF.to_string(), settings.mode.name().to_string());
+	prefs.insert(SPRINT_LINES_PREF.to_string(), settings.sprint_lines.to_string());
+	prefs.insert(ULTRA_TIME_PREF.to_string(), settings.ultra_time.to_string());
+	prefs.insert(MARATHON_LINES_PREF.to_string(), settings.marathon_lines.to_string());
+	prefs.insert(MARATHON_LEVEL_PREF.to_string(), settings.marathon_level.to_string());
+	prefs.insert(DIG_ROWS_PREF.to_string(), settings.dig_rows.to_string());
+	prefs.insert(GARBAGE_MESSINESS_PREF.to_string(), settings.garbage_messiness.to_string());
+	prefs.insert(RISE_INTERVAL_PREF.to_string(), settings.rise_interval.to_string());
+	prefs.insert(mode_rule_pref(HOLD_PREF, settings.mode), settings.hold_enabled.to_string());
+	prefs.insert(GHOST_PREF.to_string(), settings.ghost_enabled.to_string());
+	prefs.insert(LOCK_DELAY_PREF.to_string(), settings.lock_delay.to_string());
+	prefs.insert(STACK_PREF.to_string(), settings.stack.name().to_string());
+	prefs.insert(BIG_PREF.to_string(), settings.big.to_string());
+	prefs.insert(LOCK_RESET_PREF.to_string(), settings.lock_reset.name().to_string());
+	prefs.insert(DAS_PREF.to_string(), settings.das.to_string());
+	prefs.insert(ARR_PREF.to_string(), settings.arr.to_string());
+	prefs.insert(SOFT_DROP_PREF.to_string(), settings.soft_drop_factor.to_string());
+}
+
+// the custom scoring table, the guideline table if the file is missing
+fn load_scoring_table(path: &Path) -> TableScoring {
+	load_map(path).and_then(|map| TableScoring::from_map(&map)).unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		TableScoring { name: "custom".to_string(), ..TableScoring::default() }
+	})
+}
+
+// the custom gravity table, the guideline curve if the file is missing
+fn load_gravity_table(path: &Path) -> TableGravity {
+	load_map(path).and_then(|map| TableGravity::from_map(&map)).unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		TableGravity::default()
+	})
+}
+
+// tables are kept in the preferences format
+fn load_map(path: &Path) -> Result<PreferencesMap<String>, String> {
+	let mut file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+	PreferencesMap::<String>::load_from(&mut file).map_err(|e| format!("{}: {:?}", path.display(), e))
+}
+
+fn load_theme(path: &Path) -> Theme {
+	Theme::load(path).unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		Theme::default()
+	})
+}
+
+fn theme_pref_name(path: &Path) -> String {
+	path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned())
+}
+
+// the theme's font, or data/font.ttf if it can't be loaded
+fn load_font(assets: &Path, theme: &Theme) -> GlyphCache<'static> {
+	GlyphCache::new(assets.join(&theme.font), (), TextureSettings::new())
+		.or_else(|_| {
+			eprintln!("Can't load font: data/{}", theme.font);
+			GlyphCache::new(assets.join("font.ttf"), (), TextureSettings::new())
+		})
+		.expect("Missing resource: data/font.ttf")
+}
+
+fn save_prefs(pref_path: &str, prefs: &PreferencesMap<String>) {
+	let mut file = File::create(pref_path).unwrap();
+	prefs.save_to(&mut file).unwrap();
+}
+
 fn main() {
 	// Change this to OpenGL::V2_1 if not working.
 	let opengl = OpenGL::V3_2;
@@ -690,47 +1001,59 @@ fn main() {
 		.build()
 		.unwrap();
 
-	let mut game = Game::new();
 	let mut pause = false;
 
-	// Create a new game and run it.
-	let mut app = App {
-		gl: GlGraphics::new(opengl),
-		duration: 0.0,
-		last_update: 0.0
-	};
-
-	// font
 	let assets = find_folder::Search::ParentsThenKids(3, 3)
 		.for_folder("data").unwrap();
-	let ref font = assets.join("font.ttf");
-
-	if !font.exists() {
-		panic!("Missing resource: data/font.ttf");
-	}
-
-	let mut glyph_cache = GlyphCache::new(
-		font,
-		(),
-		TextureSettings::new()).unwrap();
 
-	// preferences - high score
+	// preferences - high score, settings and theme
 	let pref_path = "data/preferences.cfg";
 	let mut prefs: PreferencesMap<String> = PreferencesMap::new();
-	
-	let file_result = File::open(pref_path);
-	if file_result.is_ok() {
-		let mut file = file_result.unwrap();
-		let load_result =
-			PreferencesMap::<String>::load_from(&mut file);
-		if load_result.is_ok() {
-			let hash = load_result.unwrap();
-			if hash.contains_key(&HIGH_SCORE_PREF.to_string()) {
-				game.high_score = hash[&HIGH_SCORE_PREF.to_string()].parse().unwrap();
-			}
+
+	if let Ok(mut file) = File::open(pref_path) {
+		if let Ok(hash) = PreferencesMap::<String>::load_from(&mut file) {
+			prefs = hash;
 		}
 	}
 
+	// theme and font
+	let themes = find_themes(&assets.join("themes"));
+	let theme_name = prefs.get(THEME_PREF).cloned().unwrap_or_else(|| "classic".to_string());
+	let theme_index = themes.iter()
+		.position(|path| theme_pref_name(path) == theme_name)
+		.unwrap_or(0);
+	let theme = match themes.get(theme_index) {
+		Some(path) => load_theme(path),
+		None => Theme::default()
+	};
+
+	let mut glyph_cache = load_font(&assets, &theme);
+
+	// Create a new game and run it.
+	let mut app = App {
+		gl: GlGraphics::new(opengl),
+		duration: 0.0,
+		inputs: Inputs::default(),
+		held: Inputs::default(),
+		settings: load_settings(&prefs),
+		custom_scoring: load_scoring_table(&assets.join("scoring.cfg")),
+		custom_gravity: load_gravity_table(&assets.join("gravity.cfg")),
+		theme,
+		themes,
+		theme_index,
+		menu: OptionsMenu::default(),
+		title: true,
+		title_row: 0,
+		records: Records::default(),
+		callouts: Vec::new(),
+		callout_frames: 0,
+		outline_frames: 0
+	};
+
+	parse_args(&mut app.settings);
+
+	let mut game = app.new_game();
+
 	// audio
 	let mut sound_on = false;
 	let mut audio = Audio::new();
@@ -741,44 +1064,99 @@ fn main() {
 	audio.add("gameover", "data/gameover.wav");
 	audio.play("levelup");
 
-	generate_new_block(&mut game); // first next block is zero
-	generate_new_block(&mut game);
+	start_game(&mut game);
 
 	let mut events = Events::new(EventSettings::new());
 	while let Some(e) = events.next(&mut window) {
+		if let Some(button) = e.press_args() {
+			if !app.menu.open && !app.title {
+				set_input(&mut app.held, button, true);
+				set_input(&mut app.inputs, button, true);
+			}
+		}
+
+		// a release only shows up on the frame after the press
+		if let Some(button) = e.release_args() {
+			set_input(&mut app.held, button, false);
+		}
+
 		if let Some(Button::Keyboard(key)) = e.press_args() {
-			match key {
-				Key::Left => {
-					if !pause {
-						move_left(&mut game);
-						if sound_on {
-							audio.play("move");
-						}
-					}
-				},
-				Key::Right => {
-					if !pause {
-						move_right(&mut game);
-						if sound_on {
-							audio.play("move");
+			if app.menu.open {
+				match key {
+					Key::Up => app.menu.select_previous(),
+					Key::Down => app.menu.select_next(),
+					Key::Left | Key::Right | Key::Return => {
+						match app.menu.item() {
+							OptionItem::Theme => {
+								if !app.themes.is_empty() {
+									app.theme_index = (app.theme_index + 1) % app.themes.len();
+									let path = &app.themes[app.theme_index];
+									app.theme = load_theme(path);
+									glyph_cache = load_font(&assets, &app.theme);
+									prefs.insert(THEME_PREF.to_string(), theme_pref_name(path));
+								}
+							},
+							item => item.change(&mut app.settings)
 						}
-					}
-				},
-				Key::Down => {
-					if !pause && can_move_down(&game ) {
-						advance_block(&mut game);
-					}
-				},
-				Key::Space | Key::Up => {
-					if !pause && can_rotate(&game) {
-						rotate_block(&mut game.current_block);
-						if sound_on {
-							audio.play("rotate");
+
+						// the rest waits for the next game
+						game.settings.ghost_enabled = app.settings.ghost_enabled;
+						save_settings(&mut prefs, &app.settings);
+						save_prefs(pref_path, &prefs);
+					},
+					Key::O | Key::Escape => app.menu.open = false,
+					_ => {}
+				}
+
+				continue;
+			}
+
+			if app.title {
+				match key {
+					Key::Up | Key::Down => app.title_row = 1 - app.title_row,
+					Key::Left | Key::Right => {
+						if app.title_row == 0 {
+							next_mode(&mut app.settings);
+							load_mode_rules(&prefs, &mut app.settings);
+						} else {
+							let level = app.settings.start_level;
+							app.settings.start_level = if key == Key::Left {
+								level.max(2) - 1
+							} else {
+								level.min(MAX_START_LEVEL - 1) + 1
+							};
 						}
-					}
-				},
+
+						save_settings(&mut prefs, &app.settings);
+						save_prefs(pref_path, &prefs);
+					},
+					Key::Return => {
+						game = app.new_game();
+						game.high_score = prefs.get(&high_score_pref(&app.settings))
+							.and_then(|hs| hs.parse().ok())
+							.unwrap_or(0);
+						app.records = load_records(&prefs, &app.settings);
+						start_game(&mut game);
+						app.title = false;
+						app.callouts.clear();
+						audio.play("levelup");
+					},
+					Key::O => app.menu.open = true,
+					_ => {}
+				}
+
+				continue;
+			}
+
+			match key {
 				Key::Escape | Key::P => {
+					if let State::GameOver | State::Finished = game.state {
+						continue;
+					}
+
 					pause = !pause;
+					app.inputs = Inputs::default();
+					app.held = Inputs::default();
 					if pause {
 						game.state = State::Pause;
 					} else {
@@ -788,65 +1166,54 @@ fn main() {
 				Key::S => {
 					sound_on = !sound_on;
 				},
+				Key::O => {
+					app.menu.open = true;
+					app.inputs = Inputs::default();
+					app.held = Inputs::default();
+				},
 				Key::R => {
-					match game.state {
-						State::GameOver => {
-							let hs = game.high_score;
-							game = Game::new();
-							game.high_score = hs;
-							generate_new_block(&mut game);
-							generate_new_block(&mut game);
-							audio.play("levelup");
-						},
-						_ => {}
+					if let State::GameOver | State::Finished = game.state {
+						app.title = true;
 					}
 				}
 				_ => {}
 			}
 		}
 		
-		if let Some(Button::Mouse(key)) = e.press_args() {
-			match key {
-				MouseButton::Left => {
-					if !pause && can_rotate(&game) {
-						rotate_block(&mut game.current_block);
-						if sound_on {
-							audio.play("rotate");
-						}
-					}
-				},
-				_ => {}
-			}
-		}
-
 		if let Some(args) = e.render_args() {
 			app.render(&args, &game, &mut glyph_cache);
 		}
 
 		match game.state {
 			State::Running => {
-				if let Some(args) = e.update_args() {
+				if let Some(args) = e.update_args().filter(|_| !app.menu.open && !app.title) {
 					app.update(&args, &mut game, &mut audio, sound_on);
 				}
 			},
 			State::Pause => {
 			},
 			State::LevelDone => {
-				if game.update_interval > UPDATE_LIMIT {
-					game.update_interval -= UPDATE_STEP;
-				}
-
-				game.level += 1;
-				game.state = State::Running;
+				level_up(&mut game);
 				if sound_on {
 					audio.play("levelup");
 				}
 
 				if game.score > game.high_score {
 					game.high_score = game.score;
-					let mut file = File::create(pref_path).unwrap();
-					prefs.insert(HIGH_SCORE_PREF.to_string(), game.high_score.to_string());
-					prefs.save_to(&mut file).unwrap();
+					prefs.insert(high_score_pref(&game.settings), game.high_score.to_string());
+					save_prefs(pref_path, &prefs);
+				}
+			},
+			State::Finished => {
+				if game.score > game.high_score {
+					game.high_score = game.score;
+					prefs.insert(high_score_pref(&game.settings), game.high_score.to_string());
+					save_prefs(pref_path, &prefs);
+				}
+
+				if !app.records.saved {
+					save_records(&mut prefs, &mut app.records, &game);
+					save_prefs(pref_path, &prefs);
 				}
 			},
 			State::GameOver => {
@@ -856,9 +1223,13 @@ fn main() {
 					}
 					
 					game.high_score = game.score;
-					let mut file = File::create(pref_path).unwrap();
-					prefs.insert(HIGH_SCORE_PREF.to_string(), game.high_score.to_string());
-					prefs.save_to(&mut file).unwrap();
+					prefs.insert(high_score_pref(&game.settings), game.high_score.to_string());
+					save_prefs(pref_path, &prefs);
+				}
+
+				if !app.records.saved {
+					save_records(&mut prefs, &mut app.records, &game);
+					save_prefs(pref_path, &prefs);
 				}
 			}
 		}
@@ -0,0 +1,508 @@
+use std::str::FromStr;
+
+use crate::game::*;
+use crate::garbage::*;
+use crate::gravity::*;
+use crate::randomizer::*;
+use crate::scoring::*;
+
+
+pub const SPLIT_LINES: i64 = 10; // a sprint split every this many lines
+pub const MAX_DIG_ROWS: u32 = 16; // leaves room for blocks to spawn
+pub const RISE_SPEEDUP_PERCENT: u32 = 95; // each survival interval is this percent of the previous one
+pub const MIN_RISE_INTERVAL: u32 = 1000; // ms
+
+pub const MASTER_SECTION_LEVELS: i64 = 100;
+pub const MASTER_LAST_LEVEL: i64 = 999;
+// (ARE, lock delay, line clear delay) in frames for each master section
+pub const MASTER_TIMINGS: [(u32, u32, u32); 10] = [(27, 30, 40), (27, 30, 40), (27, 30, 40),
+	(27, 30, 40), (27, 30, 40), (25, 30, 25), (25, 28, 16), (16, 24, 12), (12, 20, 6),
+	(12, 17, 6)];
+// the score each grade starts at
+pub const MASTER_GRADES: [(&str, i64); 18] = [("9", 0), ("8", 400), ("7", 800), ("6", 1400),
+	("5", 2000), ("4", 3500), ("3", 5500), ("2", 8000), ("1", 12000), ("S1", 16000),
+	("S2", 22000), ("S3", 30000), ("S4", 40000), ("S5", 52000), ("S6", 66000),
+	("S7", 82000), ("S8", 100000), ("S9", 120000)];
+pub const MASTER_GM_SCORE: i64 = 126000;
+// GM needs these sections done by these frames: level 300 in 4:15, 500 in
+// 7:00 and 999 in 13:30
+pub const MASTER_GM_TIMES: [(usize, u64); 3] = [(2, 15300), (4, 25200), (9, 48600)];
+
+// what ends a game besides topping out
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameMode {
+	Endless, // until the stack tops out
+	Sprint, // clear settings.sprint_lines lines as fast as possible
+	Ultra, // score as much as possible in settings.ultra_time seconds
+	Marathon, // won at settings.marathon_lines lines or past settings.marathon_level
+	Dig, // clear settings.dig_rows rows of garbage as fast as possible
+	Survival, // garbage rises every settings.rise_interval ms, sooner every time
+	Master // levels 0 to 999 up to 20G, graded at the end
+}
+
+impl GameMode {
+	pub fn name(self) -> &'static str {
+		match self {
+			GameMode::Endless => "endless",
+			GameMode::Sprint => "sprint",
+			GameMode::Ultra => "ultra",
+			GameMode::Marathon => "marathon",
+			GameMode::Dig => "dig",
+			GameMode::Survival => "survival",
+			GameMode::Master => "master"
+		}
+	}
+
+	pub fn next(self) -> GameMode {
+		match self {
+			GameMode::Endless => GameMode::Sprint,
+			GameMode::Sprint => GameMode::Ultra,
+			GameMode::Ultra => GameMode::Marathon,
+			GameMode::Marathon => GameMode::Dig,
+			GameMode::Dig => GameMode::Survival,
+			GameMode::Survival => GameMode::Master,
+			GameMode::Master => GameMode::Endless
+		}
+	}
+
+	// what the mode plays with until the player picks otherwise for it;
+	// master deals like TGM and has no hold
+	pub fn default_randomizer(self) -> RandomizerKind {
+		match self {
+			GameMode::Master => RandomizerKind::History,
+			_ => RandomizerKind::Bag7
+		}
+	}
+
+	pub fn default_hold(self) -> bool {
+		self != GameMode::Master
+	}
+}
+
+impl FromStr for GameMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<GameMode, String> {
+		match s {
+			"endless" => Ok(GameMode::Endless),
+			"sprint" => Ok(GameMode::Sprint),
+			"ultra" => Ok(GameMode::Ultra),
+			"marathon" => Ok(GameMode::Marathon),
+			"dig" => Ok(GameMode::Dig),
+			"survival" => Ok(GameMode::Survival),
+			"master" => Ok(GameMode::Master),
+			_ => Err(format!("unknown mode: {}", s))
+		}
+	}
+}
+
+// seconds played, the game clock only runs while the game does
+pub fn game_time(game_state: &Game) -> f64 {
+	game_state.frame as f64 / FRAME_RATE
+}
+
+// seconds left in a timed mode
+pub fn time_left(game_state: &Game) -> f64 {
+	(game_state.settings.ultra_time as f64 - game_time(game_state)).max(0.0)
+}
+
+// pieces per second
+pub fn pieces_per_second(game_state: &Game) -> f64 {
+	let time = game_time(game_state);
+	if time > 0.0 { game_state.pieces as f64 / time } else { 0.0 }
+}
+
+// frames until the next survival garbage row, shorter after every row
+pub fn rise_interval(game_state: &Game) -> u32 {
+	let start = game_state.settings.rise_interval;
+	let speedup = RISE_SPEEDUP_PERCENT as f64 / 100.0;
+	let ms = start as f64 * speedup.powi(game_state.garbage_risen as i32);
+
+	ms_to_frames((ms as u32).max(MIN_RISE_INTERVAL.min(start)))
+}
+
+// 9 up to S9 by score, GM for reaching level 999 in time with enough points
+pub fn master_grade(game_state: &Game) -> &'static str {
+	let in_time = MASTER_GM_TIMES.iter().all(|&(section, frames)| {
+		game_state.splits.get(section).is_some_and(|&frame| frame <= frames)
+	});
+	if game_state.level >= MASTER_LAST_LEVEL && game_state.score >= MASTER_GM_SCORE && in_time {
+		return "GM";
+	}
+
+	MASTER_GRADES.iter()
+		.rev()
+		.find(|&&(_, score)| game_state.score >= score)
+		.map_or(MASTER_GRADES[0].0, |&(grade, _)| grade)
+}
+
+// gravity and delays of the master section the level is in
+fn update_master_speed(game_state: &mut Game) {
+	let section = (game_state.level / MASTER_SECTION_LEVELS) as usize;
+	let (are, lock_delay, line_clear_delay) = MASTER_TIMINGS[section.min(MASTER_TIMINGS.len() - 1)];
+
+	game_state.are = are;
+	game_state.lock_delay = lock_delay;
+	game_state.line_clear_delay = line_clear_delay;
+	game_state.gravity = game_state.gravity_curve.gravity(game_state.level);
+}
+
+// before the first block spawns
+pub fn start_mode(game_state: &mut Game) {
+	match game_state.settings.mode {
+		GameMode::Dig => {
+			let rows = game_state.settings.dig_rows.clamp(1, MAX_DIG_ROWS);
+			add_garbage(game_state, rows);
+		},
+		GameMode::Survival => game_state.next_rise = rise_interval(game_state) as u64,
+		GameMode::Master => {
+			// levels come from update_mode, never from lines_per_level
+			game_state.level = 0;
+			game_state.level_lines = i64::MAX;
+			game_state.scoring = Box::new(MasterScoring);
+			game_state.gravity_curve = Box::new(MasterGravity);
+			update_master_speed(game_state);
+		},
+		_ => {}
+	}
+}
+
+// once a frame, before the block moves
+pub fn update_mode_timer(game_state: &mut Game) {
+	match game_state.settings.mode {
+		GameMode::Ultra
+			if game_state.frame >= game_state.settings.ultra_time as u64 * FRAME_RATE as u64 => {
+			game_state.state = State::Finished;
+		},
+		GameMode::Survival if game_state.frame >= game_state.next_rise => {
+			game_state.garbage_risen += 1;
+			let fits = add_garbage(game_state, 1);
+			game_state.next_rise = game_state.frame + rise_interval(game_state) as u64;
+
+			// pushed out the top, or no room left for the falling block
+			if !fits || check_collision(game_state) {
+				game_state.state = State::GameOver;
+			}
+		},
+		_ => {}
+	}
+}
+
+// after a block locked and its rows were cleared
+pub fn update_mode(game_state: &mut Game) {
+	match game_state.settings.mode {
+		GameMode::Endless | GameMode::Ultra | GameMode::Survival => {},
+		GameMode::Marathon => {
+			// a cap of 0 is no cap
+			let settings = game_state.settings;
+			let lines_done = settings.marathon_lines > 0
+				&& game_state.lines >= settings.marathon_lines as i64;
+			let level_done = settings.marathon_level > 0
+				&& game_state.level >= settings.marathon_level as i64
+				&& game_state.lines >= game_state.level_lines;
+
+			if lines_done || level_done {
+				game_state.state = State::Finished;
+			}
+		},
+		GameMode::Sprint => {
+			let target = game_state.settings.sprint_lines as i64;
+			let lines = game_state.lines.min(target);
+			while (game_state.splits.len() as i64 + 1) * SPLIT_LINES <= lines {
+				game_state.splits.push(game_state.frame);
+			}
+
+			if game_state.lines >= target {
+				// the last split is the finish time
+				if target % SPLIT_LINES != 0 {
+					game_state.splits.push(game_state.frame);
+				}
+
+				game_state.state = State::Finished;
+			}
+		},
+		GameMode::Dig => {
+			if garbage_rows(game_state) == 0 {
+				game_state.splits.push(game_state.frame);
+				game_state.state = State::Finished;
+			}
+		},
+		GameMode::Master => {
+			// rows always count, a new block only short of a section's last
+			// level and of 998
+			let lines = game_state.last_action.map_or(0, |action| action.lines);
+			let level = (game_state.level + lines).min(MASTER_LAST_LEVEL);
+			let stop = level % MASTER_SECTION_LEVELS == MASTER_SECTION_LEVELS - 1
+				|| level == MASTER_LAST_LEVEL - 1;
+			game_state.level = if stop { level } else { level + 1 };
+
+			while (game_state.splits.len() as i64 + 1) * MASTER_SECTION_LEVELS <= game_state.level {
+				game_state.splits.push(game_state.frame);
+			}
+
+			if game_state.level >= MASTER_LAST_LEVEL {
+				game_state.splits.push(game_state.frame);
+				game_state.state = State::Finished;
+			}
+
+			update_master_speed(game_state);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::settings::*;
+
+	#[test]
+	fn master_plays_by_tgm_rules() {
+		assert_eq!(GameMode::Master.default_randomizer(), RandomizerKind::History);
+		assert!(!GameMode::Master.default_hold());
+	}
+
+	#[test]
+	fn other_modes_keep_the_usual_rules() {
+		assert_eq!(GameMode::Sprint.default_randomizer(), RandomizerKind::Bag7);
+		assert!(GameMode::Sprint.default_hold());
+	}
+
+	fn mode_game(settings: Settings) -> Game {
+		let mut game = Game::with_settings(Settings { seed: Some(1), ..settings });
+		start_game(&mut game);
+		game
+	}
+
+	// a block locked on frame, clearing lines
+	fn lock(game: &mut Game, lines: i64, frame: u64) {
+		game.frame = frame;
+		game.lines += lines;
+		game.last_action = Some(Action { lines, t_spin: TSpin::None, back_to_back: false,
+			combo: 0, perfect_clear: false, points: 0 });
+		update_mode(game);
+	}
+
+	#[test]
+	fn sprint_splits_every_ten_lines() {
+		let mut game = mode_game(Settings { mode: GameMode::Sprint, sprint_lines: 40, ..Settings::default() });
+		lock(&mut game, 4, 100);
+		lock(&mut game, 4, 200);
+		assert!(game.splits.is_empty());
+
+		lock(&mut game, 4, 300);
+		assert_eq!(game.splits, vec![300]);
+
+		for frame in [400, 500, 600, 700, 800] {
+			lock(&mut game, 4, frame);
+		}
+		assert_eq!(game.splits, vec![300, 500, 800]);
+		assert!(matches!(game.state, State::Running));
+
+		// the last split is the finish, not pushed twice
+		lock(&mut game, 4, 900);
+		lock(&mut game, 4, 1000);
+		assert_eq!(game.splits, vec![300, 500, 800, 1000]);
+		assert!(matches!(game.state, State::Finished));
+	}
+
+	#[test]
+	fn sprint_finish_off_the_split_lines() {
+		let mut game = mode_game(Settings { mode: GameMode::Sprint, sprint_lines: 21, ..Settings::default() });
+		game.lines = 14;
+		lock(&mut game, 4, 100);
+		assert_eq!(game.splits, vec![100]);
+
+		// one tetris past the 20 line split and the 21 line finish
+		lock(&mut game, 4, 200);
+		assert_eq!(game.splits, vec![100, 200, 200]);
+		assert!(matches!(game.state, State::Finished));
+	}
+
+	#[test]
+	fn marathon_ends_at_its_line_cap() {
+		let mut game = mode_game(Settings { mode: GameMode::Marathon, marathon_lines: 150,
+			marathon_level: 0, ..Settings::default() });
+		game.lines = 146;
+		lock(&mut game, 3, 100);
+		assert!(matches!(game.state, State::Running));
+
+		lock(&mut game, 1, 200);
+		assert!(matches!(game.state, State::Finished));
+	}
+
+	#[test]
+	fn marathon_ends_past_its_level_cap() {
+		let mut game = mode_game(Settings { mode: GameMode::Marathon, marathon_lines: 0,
+			marathon_level: 15, ..Settings::default() });
+		game.level = 14;
+		game.lines = game.level_lines;
+		lock(&mut game, 0, 100);
+		assert!(matches!(game.state, State::Running));
+
+		// done with level 15 rather than on reaching it
+		level_up(&mut game);
+		assert_eq!(game.level, 15);
+		lock(&mut game, 0, 200);
+		assert!(matches!(game.state, State::Running));
+
+		game.lines = game.level_lines;
+		lock(&mut game, 0, 300);
+		assert!(matches!(game.state, State::Finished));
+	}
+
+	#[test]
+	fn endless_marathon_never_ends() {
+		let mut game = mode_game(Settings { mode: GameMode::Marathon, marathon_lines: 0,
+			marathon_level: 0, ..Settings::default() });
+		game.level = 50;
+		game.lines = 10000;
+		lock(&mut game, 4, 100);
+		assert!(matches!(game.state, State::Running));
+	}
+
+	#[test]
+	fn dig_ends_with_the_last_garbage_row() {
+		let mut game = mode_game(Settings { mode: GameMode::Dig, dig_rows: 3, ..Settings::default() });
+		assert_eq!(garbage_rows(&game), 3);
+
+		load_stage(&mut game, &stage_rows(&["GGGGG.GGGG"])).unwrap();
+		lock(&mut game, 2, 100);
+		assert!(matches!(game.state, State::Running));
+		assert!(game.splits.is_empty());
+
+		load_stage(&mut game, &stage_rows(&["..TT......"])).unwrap();
+		lock(&mut game, 1, 200);
+		assert!(matches!(game.state, State::Finished));
+		assert_eq!(game.splits, vec![200]);
+	}
+
+	#[test]
+	fn rise_interval_shrinks_to_the_minimum() {
+		let mut game = Game::with_settings(Settings { rise_interval: 10000, ..Settings::default() });
+		assert_eq!(rise_interval(&game), 600);
+
+		game.garbage_risen = 1;
+		assert_eq!(rise_interval(&game), 570);
+
+		game.garbage_risen = 100;
+		assert_eq!(rise_interval(&game), ms_to_frames(MIN_RISE_INTERVAL));
+
+		// a start below the minimum holds
+		game.settings.rise_interval = 500;
+		assert_eq!(rise_interval(&game), 30);
+	}
+
+	#[test]
+	fn survival_garbage_rises_on_time() {
+		let mut game = mode_game(Settings { mode: GameMode::Survival, rise_interval: 2000, ..Settings::default() });
+		assert_eq!(game.next_rise, 120);
+
+		game.frame = 119;
+		update_mode_timer(&mut game);
+		assert_eq!(garbage_rows(&game), 0);
+
+		game.frame = 120;
+		update_mode_timer(&mut game);
+		assert_eq!(garbage_rows(&game), 1);
+		assert_eq!(game.next_rise, 120 + rise_interval(&game) as u64);
+		assert!(game.next_rise < 240);
+	}
+
+	fn master_game() -> Game {
+		mode_game(Settings { mode: GameMode::Master, ..Settings::default() })
+	}
+
+	#[test]
+	fn master_stops_short_of_each_section() {
+		let mut game = master_game();
+		assert_eq!(game.level, 0);
+		game.level = 98;
+		lock(&mut game, 0, 100);
+		assert_eq!(game.level, 99);
+
+		// only clearing rows gets past a section's last level
+		lock(&mut game, 0, 200);
+		assert_eq!(game.level, 99);
+		assert!(game.splits.is_empty());
+
+		// the row passes the stop, then the new block counts as well
+		lock(&mut game, 1, 300);
+		assert_eq!(game.level, 101);
+		assert_eq!(game.splits, vec![300]);
+	}
+
+	#[test]
+	fn master_counts_rows_before_the_block() {
+		let mut game = master_game();
+		game.level = 90;
+		lock(&mut game, 2, 100);
+		assert_eq!(game.level, 93);
+
+		// the rows reach the stop, the block can't pass it
+		game.level = 95;
+		lock(&mut game, 4, 200);
+		assert_eq!(game.level, 99);
+
+		game.level = 96;
+		lock(&mut game, 4, 300);
+		assert_eq!(game.level, 101);
+	}
+
+	#[test]
+	fn master_finishes_at_999() {
+		let mut game = master_game();
+		game.level = 997;
+		game.splits = (1..=9).collect();
+		lock(&mut game, 0, 100);
+		lock(&mut game, 0, 200);
+		assert_eq!(game.level, 998);
+		assert!(matches!(game.state, State::Running));
+
+		lock(&mut game, 4, 300);
+		assert_eq!(game.level, MASTER_LAST_LEVEL);
+		assert_eq!(game.splits.len(), 10);
+		assert_eq!(game.splits.last(), Some(&300));
+		assert!(matches!(game.state, State::Finished));
+	}
+
+	#[test]
+	fn master_timings_by_section() {
+		let mut game = master_game();
+		assert_eq!((game.are, game.lock_delay, game.line_clear_delay), MASTER_TIMINGS[0]);
+		assert_eq!(game.gravity, 4.0 / 256.0);
+
+		game.level = 499;
+		lock(&mut game, 1, 100);
+		assert_eq!((game.are, game.lock_delay, game.line_clear_delay), MASTER_TIMINGS[5]);
+		assert_eq!(game.gravity, TWENTY_G);
+
+		game.level = 950;
+		lock(&mut game, 0, 200);
+		assert_eq!((game.are, game.lock_delay, game.line_clear_delay), MASTER_TIMINGS[9]);
+	}
+
+	#[test]
+	fn master_grades() {
+		let mut game = master_game();
+		assert_eq!(master_grade(&game), "9");
+		game.score = 16000;
+		assert_eq!(master_grade(&game), "S1");
+
+		// GM takes level 999, the score and every section in time
+		game.level = MASTER_LAST_LEVEL;
+		game.score = MASTER_GM_SCORE;
+		game.splits = vec![1000; 10];
+		assert_eq!(master_grade(&game), "GM");
+
+		game.splits[4] = MASTER_GM_TIMES[1].1 + 1;
+		assert_eq!(master_grade(&game), "S9");
+
+		game.splits = vec![1000; 10];
+		game.score = MASTER_GM_SCORE - 1;
+		assert_eq!(master_grade(&game), "S9");
+
+		game.score = MASTER_GM_SCORE;
+		game.level = 998;
+		assert_eq!(master_grade(&game), "S9");
+	}
+}
@@ -0,0 +1,90 @@
+use tetris::*;
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OptionItem {
+	Theme,
+	Rotation,
+	Randomizer,
+	Scoring,
+	Gravity,
+	Hold,
+	Ghost,
+	Stack,
+	Big,
+	LockReset
+}
+
+pub const OPTION_ITEMS: [OptionItem; 10] = [OptionItem::Theme, OptionItem::Rotation,
+	OptionItem::Randomizer, OptionItem::Scoring, OptionItem::Gravity, OptionItem::Hold,
+	OptionItem::Ghost, OptionItem::Stack, OptionItem::Big, OptionItem::LockReset];
+
+impl OptionItem {
+	pub fn label(self) -> &'static str {
+		match self {
+			OptionItem::Theme => "Theme",
+			OptionItem::Rotation => "Rotation",
+			OptionItem::Randomizer => "Randomizer",
+			OptionItem::Scoring => "Scoring",
+			OptionItem::Gravity => "Gravity",
+			OptionItem::Hold => "Hold",
+			OptionItem::Ghost => "Ghost",
+			OptionItem::Stack => "Stack",
+			OptionItem::Big => "Big blocks",
+			OptionItem::LockReset => "Lock reset"
+		}
+	}
+
+	pub fn value(self, settings: &Settings, theme_name: &str) -> String {
+		let on_off = |on: bool| if on { "on" } else { "off" }.to_string();
+		match self {
+			OptionItem::Theme => theme_name.to_string(),
+			OptionItem::Rotation => settings.rotation.name().to_uppercase(),
+			OptionItem::Randomizer => settings.randomizer.name().to_uppercase(),
+			OptionItem::Scoring => settings.scoring.name().to_uppercase(),
+			OptionItem::Gravity => settings.gravity.name().to_uppercase(),
+			OptionItem::Hold => on_off(settings.hold_enabled),
+			OptionItem::Ghost => on_off(settings.ghost_enabled),
+			OptionItem::Stack => settings.stack.name().to_string(),
+			OptionItem::Big => on_off(settings.big),
+			OptionItem::LockReset => settings.lock_reset.name().to_string()
+		}
+	}
+
+	// the theme is switched by the caller, which owns the theme list
+	pub fn change(self, settings: &mut Settings) {
+		match self {
+			OptionItem::Theme => {},
+			OptionItem::Rotation => settings.rotation = settings.rotation.next(),
+			OptionItem::Randomizer => settings.randomizer = settings.randomizer.next(),
+			OptionItem::Scoring => settings.scoring = settings.scoring.next(),
+			OptionItem::Gravity => settings.gravity = settings.gravity.next(),
+			OptionItem::Hold => settings.hold_enabled = !settings.hold_enabled,
+			OptionItem::Ghost => settings.ghost_enabled = !settings.ghost_enabled,
+			OptionItem::Stack => settings.stack = settings.stack.next(),
+			OptionItem::Big => settings.big = !settings.big,
+			OptionItem::LockReset => settings.lock_reset = settings.lock_reset.next()
+		}
+	}
+}
+
+// the game is frozen while the menu is open
+#[derive(Default)]
+pub struct OptionsMenu {
+	pub open: bool,
+	pub selected: usize,
+}
+
+impl OptionsMenu {
+	pub fn item(&self) -> OptionItem {
+		OPTION_ITEMS[self.selected]
+	}
+
+	pub fn select_previous(&mut self) {
+		self.selected = (self.selected + OPTION_ITEMS.len() - 1) % OPTION_ITEMS.len();
+	}
+
+	pub fn select_next(&mut self) {
+		self.selected = (self.selected + 1) % OPTION_ITEMS.len();
+	}
+}
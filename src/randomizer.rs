@@ -0,0 +1,210 @@
+use std::str::FromStr;
+
+use rand::{Rng, RngCore};
+use rand::seq::SliceRandom;
+
+use crate::blocks::*;
+
+
+// draws go through random_index so a seed deals the same blocks everywhere
+pub trait Randomizer {
+	fn name(&self) -> &'static str;
+
+	fn next(&mut self, rng: &mut dyn RngCore) -> BlockKind;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RandomizerKind {
+	Random,
+	Bag7,
+	Bag14,
+	History
+}
+
+impl RandomizerKind {
+	pub fn create(self) -> Box<dyn Randomizer> {
+		match self {
+			RandomizerKind::Random => Box::new(PureRandomizer),
+			RandomizerKind::Bag7 => Box::new(BagRandomizer::new(1)),
+			RandomizerKind::Bag14 => Box::new(BagRandomizer::new(2)),
+			RandomizerKind::History => Box::new(HistoryRandomizer::new())
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			RandomizerKind::Random => "random",
+			RandomizerKind::Bag7 => "7bag",
+			RandomizerKind::Bag14 => "14bag",
+			RandomizerKind::History => "tgm"
+		}
+	}
+
+	pub fn next(self) -> RandomizerKind {
+		match self {
+			RandomizerKind::Random => RandomizerKind::Bag7,
+			RandomizerKind::Bag7 => RandomizerKind::Bag14,
+			RandomizerKind::Bag14 => RandomizerKind::History,
+			RandomizerKind::History => RandomizerKind::Random
+		}
+	}
+}
+
+impl FromStr for RandomizerKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<RandomizerKind, String> {
+		match s {
+			"random" => Ok(RandomizerKind::Random),
+			"7bag" => Ok(RandomizerKind::Bag7),
+			"14bag" => Ok(RandomizerKind::Bag14),
+			"tgm" => Ok(RandomizerKind::History),
+			_ => Err(format!("unknown randomizer: {}", s))
+		}
+	}
+}
+
+// sampled as u32, usize ranges depend on the platform's pointer width
+pub fn random_index(rng: &mut dyn RngCore, len: usize) -> usize {
+	rng.gen_range(0, len as u32) as usize
+}
+
+// every block has the same chance every time
+pub struct PureRandomizer;
+
+impl Randomizer for PureRandomizer {
+	fn name(&self) -> &'static str {
+		"Random"
+	}
+
+	fn next(&mut self, rng: &mut dyn RngCore) -> BlockKind {
+		BLOCK_KINDS[random_index(rng, BLOCK_KINDS.len())]
+	}
+}
+
+// deals shuffled bags holding `copies` of every block
+pub struct BagRandomizer {
+	copies: usize,
+	bag: Vec<BlockKind>,
+}
+
+impl BagRandomizer {
+	pub fn new(copies: usize) -> BagRandomizer {
+		BagRandomizer {
+			copies,
+			bag: Vec::new()
+		}
+	}
+}
+
+impl Randomizer for BagRandomizer {
+	fn name(&self) -> &'static str {
+		if self.copies == 1 { "7-bag" } else { "14-bag" }
+	}
+
+	fn next(&mut self, rng: &mut dyn RngCore) -> BlockKind {
+		if self.bag.is_empty() {
+			for _ in 0..self.copies {
+				self.bag.extend_from_slice(&BLOCK_KINDS);
+			}
+			self.bag.shuffle(rng);
+		}
+
+		self.bag.pop().unwrap()
+	}
+}
+
+const HISTORY_ROLLS: usize = 4;
+
+// TGM: reroll up to HISTORY_ROLLS times while the block is one of the last
+// four dealt; the history starts full of Z blocks and the first block is
+// never a Smashboy or a Z
+pub struct HistoryRandomizer {
+	history: [BlockKind; 4],
+	first: bool,
+}
+
+impl Default for HistoryRandomizer {
+	fn default() -> HistoryRandomizer {
+		HistoryRandomizer::new()
+	}
+}
+
+impl HistoryRandomizer {
+	pub fn new() -> HistoryRandomizer {
+		HistoryRandomizer {
+			history: [BlockKind::ClevelandZ; 4],
+			first: true
+		}
+	}
+}
+
+impl Randomizer for HistoryRandomizer {
+	fn name(&self) -> &'static str {
+		"TGM"
+	}
+
+	fn next(&mut self, rng: &mut dyn RngCore) -> BlockKind {
+		let kind = if self.first {
+			self.first = false;
+			let first = [BlockKind::OrangeRicky, BlockKind::BlueRicky,
+				BlockKind::Hero, BlockKind::Teewee];
+			first[random_index(rng, first.len())]
+		} else {
+			let mut kind = BLOCK_KINDS[random_index(rng, BLOCK_KINDS.len())];
+			for _ in 1..HISTORY_ROLLS {
+				if !self.history.contains(&kind) {
+					break;
+				}
+				kind = BLOCK_KINDS[random_index(rng, BLOCK_KINDS.len())];
+			}
+			kind
+		};
+
+		self.history.rotate_right(1);
+		self.history[0] = kind;
+
+		kind
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand_chacha::ChaCha8Rng;
+
+	use super::*;
+
+	const SEED: u64 = 12345;
+
+	fn deal(kind: RandomizerKind, count: usize) -> String {
+		let mut rng = ChaCha8Rng::seed_from_u64(SEED);
+		let mut randomizer = kind.create();
+
+		(0..count).map(|_| randomizer.next(&mut rng).letter()).collect()
+	}
+
+	// shared seeds must deal these blocks on every platform and version,
+	// changing them breaks every seed players have written down
+	#[test]
+	fn seeds_deal_the_same_blocks() {
+		assert_eq!(deal(RandomizerKind::Random, 21), "SOZOJILJOSLZIOJZJJJIO");
+		assert_eq!(deal(RandomizerKind::Bag7, 21), "SOJILZTJTOIZSLJZLIOST");
+		assert_eq!(deal(RandomizerKind::Bag14, 21), "JTOSJZLSLOIITZIZOZTOL");
+		assert_eq!(deal(RandomizerKind::History, 21), "LOJISLZOJJIZSOJLISLZJ");
+	}
+
+	#[test]
+	fn bags_hold_every_block() {
+		for (kind, copies) in [(RandomizerKind::Bag7, 1), (RandomizerKind::Bag14, 2)] {
+			let size = BLOCK_KINDS.len() * copies;
+			let blocks = deal(kind, size * 3);
+			for bag in blocks.as_bytes().chunks(size) {
+				for kind in BLOCK_KINDS.iter() {
+					let count = bag.iter().filter(|&&letter| letter as char == kind.letter()).count();
+					assert_eq!(count, copies);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,412 @@
+use std::str::FromStr;
+
+use crate::blocks::*;
+use crate::game::*;
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RotateDirection {
+	Clockwise,
+	CounterClockwise
+}
+
+impl RotateDirection {
+	pub fn apply(self, rotation: usize) -> usize {
+		match self {
+			RotateDirection::Clockwise => (rotation + 1) % 4,
+			RotateDirection::CounterClockwise => (rotation + 3) % 4
+		}
+	}
+}
+
+pub type Kick = (i32, i32);
+
+// SRS wall kick tests as published, x to the right and y up;
+// indexed by [from][clockwise, counter clockwise]
+const JLSTZ_KICKS: [[[Kick; 5]; 2]; 4] = [
+	// 0 -> R, 0 -> L
+	[[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+	 [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)]],
+	// R -> 2, R -> 0
+	[[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+	 [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)]],
+	// 2 -> L, 2 -> R
+	[[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+	 [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]],
+	// L -> 0, L -> 2
+	[[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+	 [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]],
+];
+
+const HERO_KICKS: [[[Kick; 5]; 2]; 4] = [
+	// 0 -> R, 0 -> L
+	[[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+	 [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)]],
+	// R -> 2, R -> 0
+	[[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+	 [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)]],
+	// 2 -> L, 2 -> R
+	[[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+	 [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)]],
+	// L -> 0, L -> 2
+	[[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+	 [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)]],
+];
+
+const SMASHBOY_KICKS: [Kick; 1] = [(0, 0)];
+
+// kicks to try in order, converted to stage coordinates where y grows down
+pub fn srs_kicks(kind: BlockKind, from: usize, direction: RotateDirection) -> Vec<Kick> {
+	let dir = match direction {
+		RotateDirection::Clockwise => 0,
+		RotateDirection::CounterClockwise => 1
+	};
+
+	let kicks: &[Kick] = match kind {
+		BlockKind::Smashboy => &SMASHBOY_KICKS,
+		BlockKind::Hero => &HERO_KICKS[from % 4][dir],
+		_ => &JLSTZ_KICKS[from % 4][dir]
+	};
+
+	kicks.iter().map(|&(x, y)| (x, -y)).collect()
+}
+
+pub trait RotationSystem {
+	fn name(&self) -> &'static str;
+
+	// cells of a block in one of the four orientation states (0, R, 2, L)
+	fn block(&self, kind: BlockKind, rotation: usize) -> BlockType;
+
+	fn spawn_position(&self, kind: BlockKind) -> Pos;
+
+	// offsets to try in order when the current block turns into `rotated`
+	fn kicks(&self, game_state: &Game, rotated: &BlockType,
+		direction: RotateDirection) -> Vec<Kick>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RotationSystemKind {
+	Super,
+	Arika,
+	Nintendo
+}
+
+impl RotationSystemKind {
+	pub fn create(self) -> Box<dyn RotationSystem> {
+		match self {
+			RotationSystemKind::Super => Box::new(SuperRotationSystem),
+			RotationSystemKind::Arika => Box::new(ArikaRotationSystem),
+			RotationSystemKind::Nintendo => Box::new(NintendoRotationSystem)
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			RotationSystemKind::Super => "srs",
+			RotationSystemKind::Arika => "ars",
+			RotationSystemKind::Nintendo => "nes"
+		}
+	}
+
+	pub fn next(self) -> RotationSystemKind {
+		match self {
+			RotationSystemKind::Super => RotationSystemKind::Arika,
+			RotationSystemKind::Arika => RotationSystemKind::Nintendo,
+			RotationSystemKind::Nintendo => RotationSystemKind::Super
+		}
+	}
+}
+
+impl FromStr for RotationSystemKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<RotationSystemKind, String> {
+		match s {
+			"srs" => Ok(RotationSystemKind::Super),
+			"ars" => Ok(RotationSystemKind::Arika),
+			"nes" => Ok(RotationSystemKind::Nintendo),
+			_ => Err(format!("unknown rotation system: {}", s))
+		}
+	}
+}
+
+// guideline rotation: true centre of rotation and the kick tables above
+pub struct SuperRotationSystem;
+
+impl RotationSystem for SuperRotationSystem {
+	fn name(&self) -> &'static str {
+		"SRS"
+	}
+
+	fn block(&self, kind: BlockKind, rotation: usize) -> BlockType {
+		block_cells(kind, rotation)
+	}
+
+	fn spawn_position(&self, _kind: BlockKind) -> Pos {
+		DEFAULT_START_POS
+	}
+
+	fn kicks(&self, game_state: &Game, _rotated: &BlockType,
+			direction: RotateDirection) -> Vec<Kick> {
+		srs_kicks(game_state.current_kind, game_state.current_rotation, direction)
+	}
+}
+
+type StateCells = [(usize, usize); 4];
+
+// orientation states as (x, y) cells in the block's box, in clockwise order
+fn cells_block(states: &[[StateCells; 4]; 7], kind: BlockKind, rotation: usize) -> BlockType {
+	block_from_cells(&states[kind as usize][rotation % 4])
+}
+
+// pieces lie flat against the bottom of their box, so they spawn one row up
+const ARIKA_STATES: [[StateCells; 4]; 7] = [
+	// Smashboy
+	[[(1, 1), (2, 1), (1, 2), (2, 2)], [(1, 1), (2, 1), (1, 2), (2, 2)],
+	 [(1, 1), (2, 1), (1, 2), (2, 2)], [(1, 1), (2, 1), (1, 2), (2, 2)]],
+	// Orange Ricky
+	[[(0, 1), (1, 1), (2, 1), (0, 2)], [(0, 0), (1, 0), (1, 1), (1, 2)],
+	 [(2, 1), (0, 2), (1, 2), (2, 2)], [(1, 0), (1, 1), (1, 2), (2, 2)]],
+	// Blue Ricky
+	[[(0, 1), (1, 1), (2, 1), (2, 2)], [(1, 0), (1, 1), (0, 2), (1, 2)],
+	 [(0, 1), (0, 2), (1, 2), (2, 2)], [(1, 0), (2, 0), (1, 1), (1, 2)]],
+	// Cleveland Z
+	[[(0, 1), (1, 1), (1, 2), (2, 2)], [(2, 0), (1, 1), (2, 1), (1, 2)],
+	 [(0, 1), (1, 1), (1, 2), (2, 2)], [(2, 0), (1, 1), (2, 1), (1, 2)]],
+	// Rhode Island Z
+	[[(1, 1), (2, 1), (0, 2), (1, 2)], [(0, 0), (0, 1), (1, 1), (1, 2)],
+	 [(1, 1), (2, 1), (0, 2), (1, 2)], [(0, 0), (0, 1), (1, 1), (1, 2)]],
+	// Hero
+	[[(0, 1), (1, 1), (2, 1), (3, 1)], [(2, 0), (2, 1), (2, 2), (2, 3)],
+	 [(0, 1), (1, 1), (2, 1), (3, 1)], [(2, 0), (2, 1), (2, 2), (2, 3)]],
+	// Teewee
+	[[(0, 1), (1, 1), (2, 1), (1, 2)], [(1, 0), (0, 1), (1, 1), (1, 2)],
+	 [(1, 1), (0, 2), (1, 2), (2, 2)], [(1, 0), (1, 1), (2, 1), (1, 2)]],
+];
+
+// TGM style: bottom aligned, try one step right then left, the Hero never kicks
+pub struct ArikaRotationSystem;
+
+impl RotationSystem for ArikaRotationSystem {
+	fn name(&self) -> &'static str {
+		"ARS"
+	}
+
+	fn block(&self, kind: BlockKind, rotation: usize) -> BlockType {
+		cells_block(&ARIKA_STATES, kind, rotation)
+	}
+
+	fn spawn_position(&self, _kind: BlockKind) -> Pos {
+		Pos{x: DEFAULT_START_POS.x, y: DEFAULT_START_POS.y - 1}
+	}
+
+	fn kicks(&self, game_state: &Game, rotated: &BlockType,
+			_direction: RotateDirection) -> Vec<Kick> {
+		let kind = game_state.current_kind;
+		if kind == BlockKind::Hero || kind == BlockKind::Smashboy {
+			return vec![(0, 0)];
+		}
+
+		// centre column rule: Ricky and Teewee blocks don't kick when the
+		// first blocked cell, read row by row, is in the middle column
+		let centre_check = kind == BlockKind::OrangeRicky
+			|| kind == BlockKind::BlueRicky
+			|| kind == BlockKind::Teewee;
+
+		if centre_check && first_blocked_column(game_state, rotated) == Some(1) {
+			return vec![(0, 0)];
+		}
+
+		vec![(0, 0), (1, 0), (-1, 0)]
+	}
+}
+
+fn first_blocked_column(game_state: &Game, rotated: &BlockType) -> Option<usize> {
+	let pos = game_state.current_position;
+	for y in 0..3 {
+		for x in 0..3 {
+			if *rotated.get(x, y).unwrap() && !block_cell_free(game_state, pos, x as i32, y as i32) {
+				return Some(x);
+			}
+		}
+	}
+
+	None
+}
+
+// centred rotation with right handed Z blocks and no kicks
+const NINTENDO_STATES: [[StateCells; 4]; 7] = [
+	// Smashboy
+	[[(1, 1), (2, 1), (1, 2), (2, 2)], [(1, 1), (2, 1), (1, 2), (2, 2)],
+	 [(1, 1), (2, 1), (1, 2), (2, 2)], [(1, 1), (2, 1), (1, 2), (2, 2)]],
+	// Orange Ricky
+	[[(0, 1), (1, 1), (2, 1), (0, 2)], [(0, 0), (1, 0), (1, 1), (1, 2)],
+	 [(2, 0), (0, 1), (1, 1), (2, 1)], [(1, 0), (1, 1), (1, 2), (2, 2)]],
+	// Blue Ricky
+	[[(0, 1), (1, 1), (2, 1), (2, 2)], [(1, 0), (1, 1), (0, 2), (1, 2)],
+	 [(0, 0), (0, 1), (1, 1), (2, 1)], [(1, 0), (2, 0), (1, 1), (1, 2)]],
+	// Cleveland Z
+	[[(0, 1), (1, 1), (1, 2), (2, 2)], [(2, 0), (1, 1), (2, 1), (1, 2)],
+	 [(0, 1), (1, 1), (1, 2), (2, 2)], [(2, 0), (1, 1), (2, 1), (1, 2)]],
+	// Rhode Island Z
+	[[(1, 1), (2, 1), (0, 2), (1, 2)], [(1, 0), (1, 1), (2, 1), (2, 2)],
+	 [(1, 1), (2, 1), (0, 2), (1, 2)], [(1, 0), (1, 1), (2, 1), (2, 2)]],
+	// Hero
+	[[(0, 2), (1, 2), (2, 2), (3, 2)], [(2, 0), (2, 1), (2, 2), (2, 3)],
+	 [(0, 2), (1, 2), (2, 2), (3, 2)], [(2, 0), (2, 1), (2, 2), (2, 3)]],
+	// Teewee
+	[[(0, 1), (1, 1), (2, 1), (1, 2)], [(1, 0), (0, 1), (1, 1), (1, 2)],
+	 [(1, 0), (0, 1), (1, 1), (2, 1)], [(1, 0), (1, 1), (2, 1), (1, 2)]],
+];
+
+pub struct NintendoRotationSystem;
+
+impl RotationSystem for NintendoRotationSystem {
+	fn name(&self) -> &'static str {
+		"NES"
+	}
+
+	fn block(&self, kind: BlockKind, rotation: usize) -> BlockType {
+		cells_block(&NINTENDO_STATES, kind, rotation)
+	}
+
+	fn spawn_position(&self, kind: BlockKind) -> Pos {
+		let rows_above = if kind == BlockKind::Hero { 2 } else { 1 };
+
+		Pos{x: DEFAULT_START_POS.x, y: DEFAULT_START_POS.y - rows_above}
+	}
+
+	fn kicks(&self, _game_state: &Game, _rotated: &BlockType,
+			_direction: RotateDirection) -> Vec<Kick> {
+		vec![(0, 0)]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::settings::*;
+
+	fn opposite(direction: RotateDirection) -> RotateDirection {
+		match direction {
+			RotateDirection::Clockwise => RotateDirection::CounterClockwise,
+			RotateDirection::CounterClockwise => RotateDirection::Clockwise
+		}
+	}
+
+	#[test]
+	fn kicks_turn_y_down() {
+		assert_eq!(srs_kicks(BlockKind::Teewee, 0, RotateDirection::Clockwise),
+			vec![(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]);
+		assert_eq!(srs_kicks(BlockKind::Hero, 0, RotateDirection::Clockwise),
+			vec![(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)]);
+		assert_eq!(srs_kicks(BlockKind::Hero, 3, RotateDirection::CounterClockwise),
+			vec![(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)]);
+		assert_eq!(srs_kicks(BlockKind::Smashboy, 2, RotateDirection::Clockwise), vec![(0, 0)]);
+	}
+
+	// rotating back undoes a kick: the tests from B to A are those from A
+	// to B turned around
+	#[test]
+	fn kicks_back_are_mirrored() {
+		for &kind in BLOCK_KINDS.iter() {
+			for from in 0..4 {
+				for &direction in [RotateDirection::Clockwise, RotateDirection::CounterClockwise].iter() {
+					let to = direction.apply(from);
+					let back: Vec<Kick> = srs_kicks(kind, to, opposite(direction)).iter()
+						.map(|&(x, y)| (-x, -y))
+						.collect();
+					assert_eq!(srs_kicks(kind, from, direction), back, "{:?} {}", kind, from);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn wall_kick() {
+		let mut game = Game::new();
+		start_game(&mut game);
+		game.current_kind = BlockKind::Teewee;
+		game.current_rotation = 1;
+		game.current_block = game.rotation_system.block(BlockKind::Teewee, 1);
+		game.current_position = Pos{x: -1, y: 5}; // against the left wall
+
+		// the rotated T doesn't fit in place, the second test moves it right
+		assert!(rotate(&mut game, RotateDirection::Clockwise));
+		assert_eq!(game.current_rotation, 2);
+		assert_eq!(game.current_position, Pos{x: 0, y: 5});
+		assert_eq!(game.last_kick, Some((1, 0)));
+	}
+
+	#[test]
+	fn no_rotation_without_a_fitting_kick() {
+		let mut game = Game::new();
+		start_game(&mut game);
+		game.current_kind = BlockKind::Hero;
+		game.current_rotation = 0;
+		game.current_block = game.rotation_system.block(BlockKind::Hero, 0);
+		game.current_position = Pos{x: 3, y: 17};
+
+		// a tunnel one row high, just long enough for the flat Hero
+		let mut text = "GGGGGGGGGG\n".repeat(STAGE_HEIGHT - 2);
+		text.push_str("GGG....GGG\nGGGGGGGGGG\n");
+		load_stage(&mut game, &text).unwrap();
+		assert!(!check_collision(&game));
+
+		assert!(!can_rotate(&game, RotateDirection::Clockwise));
+		assert!(!rotate(&mut game, RotateDirection::Clockwise));
+		assert_eq!(game.current_rotation, 0);
+	}
+
+	fn ars_game(kind: BlockKind, rotation: usize, pos: Pos) -> Game {
+		let mut game = Game::with_settings(Settings { rotation: RotationSystemKind::Arika, ..Settings::default() });
+		start_game(&mut game);
+		game.current_kind = kind;
+		game.current_rotation = rotation;
+		game.current_block = game.rotation_system.block(kind, rotation);
+		game.current_position = pos;
+		game
+	}
+
+	#[test]
+	fn ars_kicks_right_then_left() {
+		// off the left wall, one step right
+		let mut game = ars_game(BlockKind::Teewee, 3, Pos{x: -1, y: 5});
+		assert!(rotate(&mut game, RotateDirection::Clockwise));
+		assert_eq!(game.current_position, Pos{x: 0, y: 5});
+		assert_eq!(game.last_kick, Some((1, 0)));
+
+		// off the right wall, right doesn't fit either so one step left
+		let mut game = ars_game(BlockKind::Teewee, 1, Pos{x: 8, y: 5});
+		assert!(rotate(&mut game, RotateDirection::Clockwise));
+		assert_eq!(game.current_position, Pos{x: 7, y: 5});
+		assert_eq!(game.last_kick, Some((-1, 0)));
+	}
+
+	#[test]
+	fn ars_centre_column_blocks_kicks() {
+		// the T's stem would turn up into a filled cell above its middle; a
+		// kick right would fit but the centre column rule forbids it
+		let mut game = ars_game(BlockKind::Teewee, 0, Pos{x: 3, y: 10});
+		game.set_stage(4, 10, Cell::Garbage);
+		assert!(!check_collision(&game));
+
+		assert!(!rotate(&mut game, RotateDirection::Clockwise));
+		assert_eq!(game.current_rotation, 0);
+		assert_eq!(game.current_position, Pos{x: 3, y: 10});
+	}
+
+	#[test]
+	fn ars_and_nes_blocks_spawn_on_the_top_row() {
+		let systems: [Box<dyn RotationSystem>; 2] = [Box::new(ArikaRotationSystem), Box::new(NintendoRotationSystem)];
+		for system in systems.iter() {
+			for &kind in BLOCK_KINDS.iter() {
+				let block = system.block(kind, 0);
+				let top = (0..BLOCK_SIZE)
+					.find(|&y| (0..BLOCK_SIZE).any(|x| *block.get(x, y).unwrap()))
+					.unwrap();
+				assert_eq!(system.spawn_position(kind).y + top as i32, 0, "{} {:?}", system.name(), kind);
+			}
+		}
+	}
+}
@@ -0,0 +1,535 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::blocks::*;
+use crate::game::*;
+
+
+// guideline points, multiplied by the level
+pub const LINE_SCORES: [i64; 5] = [0, 100, 300, 500, 800]; // by rows cleared
+pub const T_SPIN_SCORES: [i64; 4] = [400, 800, 1200, 1600];
+pub const T_SPIN_MINI_SCORES: [i64; 3] = [100, 200, 400];
+pub const PERFECT_CLEAR_SCORES: [i64; 5] = [0, 800, 1200, 1800, 2000];
+pub const BACK_TO_BACK_PERFECT_CLEAR_SCORE: i64 = 3200; // back-to-back tetris
+pub const BACK_TO_BACK_PERCENT: i64 = 150;
+pub const COMBO_SCORE: i64 = 50; // per clear in a row after the first
+pub const SOFT_DROP_SCORE: i64 = 1; // per cell
+pub const HARD_DROP_SCORE: i64 = 2; // per cell
+
+// NES points, multiplied by level + 1
+pub const NES_LINE_SCORES: [i64; 5] = [0, 40, 100, 300, 1200];
+
+pub const MASTER_BRAVO: i64 = 4; // multiplier for a perfect clear
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TSpin {
+	None,
+	Mini,
+	Full
+}
+
+// what a locked block scored, for callouts
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Action {
+	pub lines: i64,
+	pub t_spin: TSpin,
+	pub back_to_back: bool,
+	pub combo: i64, // clears in a row before this one
+	pub perfect_clear: bool,
+	pub points: i64,
+}
+
+impl Action {
+	// e.g. ["BACK-TO-BACK", "T-SPIN DOUBLE", "2 COMBO"]
+	pub fn callouts(&self) -> Vec<String> {
+		let mut callouts = Vec::new();
+		if self.back_to_back {
+			callouts.push("BACK-TO-BACK".to_string());
+		}
+
+		let spin = match self.t_spin {
+			TSpin::None => "",
+			TSpin::Mini => "T-SPIN MINI",
+			TSpin::Full => "T-SPIN"
+		};
+		let lines = match self.lines {
+			0 => "",
+			1 => "SINGLE",
+			2 => "DOUBLE",
+			3 => "TRIPLE",
+			_ => "TETRIS"
+		};
+		let name = format!("{} {}", spin, lines);
+		if !name.trim().is_empty() {
+			callouts.push(name.trim().to_string());
+		}
+
+		if self.combo > 0 {
+			callouts.push(format!("{} COMBO", self.combo));
+		}
+
+		if self.perfect_clear {
+			callouts.push("PERFECT CLEAR".to_string());
+		}
+
+		callouts
+	}
+}
+
+// how a locked block and drops are turned into points
+pub trait ScoringRules {
+	fn name(&self) -> &str;
+
+	// the action has everything filled in but the points
+	fn points(&self, action: &Action, level: i64) -> i64;
+
+	fn soft_drop_points(&self) -> i64; // per cell
+
+	fn hard_drop_points(&self) -> i64; // per cell
+
+	// T-spins, back-to-back, combos and perfect clears
+	fn bonuses(&self) -> bool {
+		true
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScoringKind {
+	Guideline,
+	Nes,
+	Custom
+}
+
+impl ScoringKind {
+	// Custom starts from the guideline table until the frontend swaps in
+	// the table it loaded
+	pub fn create(self) -> Box<dyn ScoringRules> {
+		match self {
+			ScoringKind::Guideline => Box::new(TableScoring::default()),
+			ScoringKind::Nes => Box::new(NesScoring),
+			ScoringKind::Custom => Box::new(TableScoring {
+				name: "custom".to_string(),
+				..TableScoring::default()
+			})
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			ScoringKind::Guideline => "guideline",
+			ScoringKind::Nes => "nes",
+			ScoringKind::Custom => "custom"
+		}
+	}
+
+	pub fn next(self) -> ScoringKind {
+		match self {
+			ScoringKind::Guideline => ScoringKind::Nes,
+			ScoringKind::Nes => ScoringKind::Custom,
+			ScoringKind::Custom => ScoringKind::Guideline
+		}
+	}
+}
+
+impl FromStr for ScoringKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<ScoringKind, String> {
+		match s {
+			"guideline" => Ok(ScoringKind::Guideline),
+			"nes" => Ok(ScoringKind::Nes),
+			"custom" => Ok(ScoringKind::Custom),
+			_ => Err(format!("unknown scoring: {}", s))
+		}
+	}
+}
+
+// lines only, no hard drop points
+pub struct NesScoring;
+
+impl ScoringRules for NesScoring {
+	fn name(&self) -> &str {
+		"nes"
+	}
+
+	fn points(&self, action: &Action, level: i64) -> i64 {
+		NES_LINE_SCORES[action.lines.min(4) as usize] * (nes_level(level) + 1)
+	}
+
+	fn soft_drop_points(&self) -> i64 {
+		1
+	}
+
+	fn hard_drop_points(&self) -> i64 {
+		0
+	}
+
+	fn bonuses(&self) -> bool {
+		false
+	}
+}
+
+// after TGM: (level + rows) / 4 rounded up, times rows, clears in a row and
+// perfect clears
+pub struct MasterScoring;
+
+impl ScoringRules for MasterScoring {
+	fn name(&self) -> &str {
+		"master"
+	}
+
+	fn points(&self, action: &Action, level: i64) -> i64 {
+		let bravo = if action.perfect_clear { MASTER_BRAVO } else { 1 };
+
+		(level + action.lines + 3) / 4 * action.lines * (action.combo + 1) * bravo
+	}
+
+	fn soft_drop_points(&self) -> i64 {
+		1
+	}
+
+	fn hard_drop_points(&self) -> i64 {
+		2
+	}
+}
+
+// keys of a custom scoring table, by rows cleared
+const LINE_KEYS: [&str; 5] = ["", "single", "double", "triple", "tetris"];
+const T_SPIN_KEYS: [&str; 4] = ["t_spin", "t_spin_single", "t_spin_double", "t_spin_triple"];
+const T_SPIN_MINI_KEYS: [&str; 3] = ["t_spin_mini", "t_spin_mini_single", "t_spin_mini_double"];
+const PERFECT_CLEAR_KEYS: [&str; 5] = ["", "perfect_clear_single", "perfect_clear_double",
+	"perfect_clear_triple", "perfect_clear_tetris"];
+
+// guideline style scoring from a table, the default is the guideline
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableScoring {
+	pub name: String,
+	pub lines: [i64; 5], // by rows cleared
+	pub t_spin: [i64; 4],
+	pub t_spin_mini: [i64; 3],
+	pub perfect_clear: [i64; 5],
+	pub back_to_back_perfect_clear: i64,
+	pub back_to_back_percent: i64,
+	pub combo: i64,
+	pub soft_drop: i64,
+	pub hard_drop: i64,
+	pub level_offset: i64, // points are multiplied by level + level_offset
+}
+
+impl Default for TableScoring {
+	fn default() -> TableScoring {
+		TableScoring {
+			name: "guideline".to_string(),
+			lines: LINE_SCORES,
+			t_spin: T_SPIN_SCORES,
+			t_spin_mini: T_SPIN_MINI_SCORES,
+			perfect_clear: PERFECT_CLEAR_SCORES,
+			back_to_back_perfect_clear: BACK_TO_BACK_PERFECT_CLEAR_SCORE,
+			back_to_back_percent: BACK_TO_BACK_PERCENT,
+			combo: COMBO_SCORE,
+			soft_drop: SOFT_DROP_SCORE,
+			hard_drop: HARD_DROP_SCORE,
+			level_offset: 0
+		}
+	}
+}
+
+impl TableScoring {
+	// keys missing from the map keep their guideline value, see
+	// data/scoring.cfg for the names
+	pub fn from_map(map: &HashMap<String, String>) -> Result<TableScoring, String> {
+		let mut table = TableScoring {
+			name: map.get("name").cloned().unwrap_or_else(|| "custom".to_string()),
+			..TableScoring::default()
+		};
+
+		let mut values: Vec<(&str, &mut i64)> = vec![
+			("back_to_back_perfect_clear", &mut table.back_to_back_perfect_clear),
+			("back_to_back_percent", &mut table.back_to_back_percent),
+			("combo", &mut table.combo),
+			("soft_drop", &mut table.soft_drop),
+			("hard_drop", &mut table.hard_drop),
+			("level_offset", &mut table.level_offset)];
+
+		values.extend(LINE_KEYS.iter().copied().zip(table.lines.iter_mut()).skip(1));
+		values.extend(T_SPIN_KEYS.iter().copied().zip(table.t_spin.iter_mut()));
+		values.extend(T_SPIN_MINI_KEYS.iter().copied().zip(table.t_spin_mini.iter_mut()));
+		values.extend(PERFECT_CLEAR_KEYS.iter().copied().zip(table.perfect_clear.iter_mut()).skip(1));
+
+		for (key, value) in values {
+			if let Some(text) = map.get(key) {
+				*value = text.parse().map_err(|_| format!("bad number for {}: {}", key, text))?;
+			}
+		}
+
+		Ok(table)
+	}
+}
+
+impl ScoringRules for TableScoring {
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn points(&self, action: &Action, level: i64) -> i64 {
+		let lines = action.lines;
+		let multiplier = level + self.level_offset;
+		let mut points = match action.t_spin {
+			TSpin::None => self.lines[lines.min(4) as usize],
+			TSpin::Mini => self.t_spin_mini[lines.min(2) as usize],
+			TSpin::Full => self.t_spin[lines.min(3) as usize]
+		} * multiplier;
+
+		if action.back_to_back {
+			points = points * self.back_to_back_percent / 100;
+		}
+
+		points += self.combo * action.combo * multiplier;
+
+		if action.perfect_clear {
+			points += if action.back_to_back && lines >= 4 {
+				self.back_to_back_perfect_clear
+			} else {
+				self.perfect_clear[lines.min(4) as usize]
+			} * multiplier;
+		}
+
+		points
+	}
+
+	fn soft_drop_points(&self) -> i64 {
+		self.soft_drop
+	}
+
+	fn hard_drop_points(&self) -> i64 {
+		self.hard_drop
+	}
+}
+
+// 3-corner rule: a T whose last move was a rotation, with three of the four
+// cells diagonal to its centre blocked. It's a mini unless both corners on
+// the pointing side are blocked or the rotation needed the long (1, 2) kick.
+pub fn detect_t_spin(game_state: &Game) -> TSpin {
+	let kick = match game_state.last_kick {
+		Some(kick) if game_state.current_kind == BlockKind::Teewee => kick,
+		_ => return TSpin::None
+	};
+
+	let block = &game_state.current_block;
+	let in_block = |x: i32, y: i32| x >= 0 && y >= 0
+		&& x < BLOCK_SIZE as i32 && y < BLOCK_SIZE as i32
+		&& *block.get(x as usize, y as usize).unwrap();
+	let sides = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+	// the centre is the cell with three neighbours, the T points away
+	// from the missing one
+	let mut centre = None;
+	for x in 0..BLOCK_SIZE as i32 {
+		for y in 0..BLOCK_SIZE as i32 {
+			if !in_block(x, y) {
+				continue;
+			}
+
+			let missing: Vec<&(i32, i32)> = sides.iter()
+				.filter(|(dx, dy)| !in_block(x + dx, y + dy))
+				.collect();
+			if missing.len() == 1 {
+				centre = Some((x, y, -missing[0].0, -missing[0].1));
+			}
+		}
+	}
+
+	let (x, y, dx, dy) = match centre {
+		Some(centre) => centre,
+		None => return TSpin::None
+	};
+
+	let pos = game_state.current_position;
+	let blocked = |cx: i32, cy: i32| !block_cell_free(game_state, pos, x + cx, y + cy);
+
+	// sideways from the pointing direction
+	let (px, py) = (dy, dx);
+	let front = blocked(dx + px, dy + py) as i32 + blocked(dx - px, dy - py) as i32;
+	let back = blocked(-dx + px, -dy + py) as i32 + blocked(-dx - px, -dy - py) as i32;
+
+	if front + back < 3 {
+		TSpin::None
+	} else if front == 2 || (kick.0.abs() == 1 && kick.1.abs() == 2) {
+		TSpin::Full
+	} else {
+		TSpin::Mini
+	}
+}
+
+// award points for a locked block once its rows are gone
+pub fn score_lock(game_state: &mut Game, lines: i64, t_spin: TSpin) -> Option<Action> {
+	let bonuses = game_state.scoring.bonuses();
+	let t_spin = if bonuses { t_spin } else { TSpin::None };
+
+	if lines == 0 {
+		game_state.combo = -1;
+		if t_spin == TSpin::None {
+			return None;
+		}
+	}
+
+	// tetrises and T-spins that clear rows are difficult, one after
+	// another they are worth more
+	let difficult = lines >= 4 || (lines > 0 && t_spin != TSpin::None);
+	let back_to_back = bonuses && difficult && game_state.back_to_back;
+
+	if lines > 0 {
+		game_state.back_to_back = difficult;
+		game_state.combo += 1;
+	}
+
+	let mut action = Action {
+		lines,
+		t_spin,
+		back_to_back,
+		combo: if bonuses { game_state.combo.max(0) } else { 0 },
+		perfect_clear: bonuses && lines > 0 && stage_empty(game_state),
+		points: 0
+	};
+
+	action.points = game_state.scoring.points(&action, game_state.level);
+	game_state.inc_score(action.points);
+
+	Some(action)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::rotation::*;
+	use crate::settings::*;
+
+	// rows written from the bottom up, padded with empty rows on top
+	fn game_with(rows: &[&str]) -> Game {
+		let mut game = Game::with_settings(Settings { seed: Some(1), ..Settings::default() });
+		start_game(&mut game);
+		load_stage(&mut game, &stage_rows(rows)).unwrap();
+		game
+	}
+
+	// a T in rotation 2 (pointing down) with its centre at (4, 18)
+	fn t_slot(rows: &[&str], kick: Option<Kick>) -> Game {
+		let mut game = game_with(rows);
+		game.current_kind = BlockKind::Teewee;
+		game.current_rotation = 2;
+		game.current_block = game.rotation_system.block(BlockKind::Teewee, 2);
+		game.current_position = Pos{x: 3, y: 17};
+		game.last_kick = kick;
+		game
+	}
+
+	const TSD: [&str; 3] = ["GGGG.GGGGG", "GGG...GGGG", "GGGG......"];
+	const MINI: [&str; 3] = ["GGGG..GGGG", "GGG...GGGG", "GGGG.G...."];
+
+	#[test]
+	fn t_spin_double() {
+		let mut game = t_slot(&TSD, Some((0, 0)));
+		assert_eq!(detect_t_spin(&game), TSpin::Full);
+
+		assert_eq!(lock_block(&mut game), 2);
+		let action = game.last_action.unwrap();
+		assert_eq!(action.t_spin, TSpin::Full);
+		assert_eq!(action.points, 1200);
+		assert!(game.back_to_back);
+	}
+
+	#[test]
+	fn one_front_corner_is_a_mini() {
+		let mut game = t_slot(&MINI, Some((0, 0)));
+		assert_eq!(detect_t_spin(&game), TSpin::Mini);
+
+		assert_eq!(lock_block(&mut game), 1);
+		assert_eq!(game.last_action.unwrap().points, T_SPIN_MINI_SCORES[1]);
+	}
+
+	#[test]
+	fn long_kick_upgrades_a_mini() {
+		assert_eq!(detect_t_spin(&t_slot(&MINI, Some((1, 2)))), TSpin::Full);
+		assert_eq!(detect_t_spin(&t_slot(&MINI, Some((-1, -2)))), TSpin::Full);
+		assert_eq!(detect_t_spin(&t_slot(&MINI, Some((1, 0)))), TSpin::Mini);
+	}
+
+	#[test]
+	fn no_t_spin_without_rotation_or_corners() {
+		// moved after the last rotation
+		assert_eq!(detect_t_spin(&t_slot(&TSD, None)), TSpin::None);
+
+		// only two corners blocked
+		let open = ["GGGG.GGGGG", "GGG...GGGG", ".........."];
+		assert_eq!(detect_t_spin(&t_slot(&open, Some((0, 0)))), TSpin::None);
+
+		let mut game = t_slot(&TSD, Some((0, 0)));
+		game.current_kind = BlockKind::BlueRicky;
+		assert_eq!(detect_t_spin(&game), TSpin::None);
+	}
+
+	#[test]
+	fn back_to_back_t_spin() {
+		let mut game = t_slot(&TSD, Some((0, 0)));
+		game.back_to_back = true;
+
+		lock_block(&mut game);
+		let action = game.last_action.unwrap();
+		assert!(action.back_to_back);
+		assert_eq!(action.points, 1200 * BACK_TO_BACK_PERCENT / 100);
+	}
+
+	#[test]
+	fn a_single_breaks_back_to_back() {
+		let mut game = game_with(&["G........."]);
+		game.back_to_back = true;
+
+		let action = score_lock(&mut game, 1, TSpin::None).unwrap();
+		assert!(!action.back_to_back);
+		assert!(!game.back_to_back);
+		assert_eq!(action.points, LINE_SCORES[1]);
+	}
+
+	#[test]
+	fn combos_count_clears_in_a_row() {
+		let mut game = game_with(&["G........."]);
+
+		assert_eq!(score_lock(&mut game, 1, TSpin::None).unwrap().points, 100);
+		let action = score_lock(&mut game, 2, TSpin::None).unwrap();
+		assert_eq!(action.combo, 1);
+		assert_eq!(action.points, 300 + COMBO_SCORE);
+		assert_eq!(score_lock(&mut game, 1, TSpin::None).unwrap().points, 100 + 2 * COMBO_SCORE);
+
+		assert_eq!(score_lock(&mut game, 0, TSpin::None), None);
+		assert_eq!(game.combo, -1);
+		assert_eq!(score_lock(&mut game, 1, TSpin::None).unwrap().combo, 0);
+	}
+
+	#[test]
+	fn perfect_clears() {
+		let mut game = game_with(&[]);
+		let action = score_lock(&mut game, 4, TSpin::None).unwrap();
+		assert!(action.perfect_clear);
+		assert_eq!(action.points, 800 + PERFECT_CLEAR_SCORES[4]);
+
+		// a back-to-back tetris perfect clear
+		game.combo = -1;
+		let action = score_lock(&mut game, 4, TSpin::None).unwrap();
+		assert_eq!(action.points, 800 * BACK_TO_BACK_PERCENT / 100 + BACK_TO_BACK_PERFECT_CLEAR_SCORE);
+	}
+
+	#[test]
+	fn nes_scoring_has_no_bonuses() {
+		let mut game = t_slot(&TSD, Some((0, 0)));
+		game.scoring = ScoringKind::Nes.create();
+		game.back_to_back = true;
+
+		lock_block(&mut game);
+		let action = game.last_action.unwrap();
+		assert_eq!(action.t_spin, TSpin::None);
+		assert!(!action.back_to_back);
+		// level 1 is the NES's level 0, paid at the table's own values
+		assert_eq!(game.level, 1);
+		assert_eq!(action.points, NES_LINE_SCORES[2]);
+	}
+}
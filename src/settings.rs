@@ -0,0 +1,143 @@
+use std::str::FromStr;
+
+use crate::gravity::*;
+use crate::mode::*;
+use crate::randomizer::*;
+use crate::rotation::*;
+use crate::scoring::*;
+
+
+// what moving or rotating a block resting on the stage does to its lock delay
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LockReset {
+	Move, // restarts it, up to MAX_LOCK_RESETS times per row
+	Classic // nothing, only falling to a lower row restarts it
+}
+
+impl LockReset {
+	pub fn name(self) -> &'static str {
+		match self {
+			LockReset::Move => "move",
+			LockReset::Classic => "classic"
+		}
+	}
+
+	pub fn next(self) -> LockReset {
+		match self {
+			LockReset::Move => LockReset::Classic,
+			LockReset::Classic => LockReset::Move
+		}
+	}
+}
+
+impl FromStr for LockReset {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<LockReset, String> {
+		match s {
+			"move" => Ok(LockReset::Move),
+			"classic" => Ok(LockReset::Classic),
+			_ => Err(format!("unknown lock reset: {}", s))
+		}
+	}
+}
+
+// how long locked cells stay on screen, see cell_visibility
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StackVisibility {
+	Shown,
+	Fading, // gone FADE_DELAY + FADE_FRAMES frames after locking
+	Invisible // only the falling block shows
+}
+
+impl StackVisibility {
+	pub fn name(self) -> &'static str {
+		match self {
+			StackVisibility::Shown => "shown",
+			StackVisibility::Fading => "fading",
+			StackVisibility::Invisible => "invisible"
+		}
+	}
+
+	pub fn next(self) -> StackVisibility {
+		match self {
+			StackVisibility::Shown => StackVisibility::Fading,
+			StackVisibility::Fading => StackVisibility::Invisible,
+			StackVisibility::Invisible => StackVisibility::Shown
+		}
+	}
+}
+
+impl FromStr for StackVisibility {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<StackVisibility, String> {
+		match s {
+			"shown" => Ok(StackVisibility::Shown),
+			"fading" => Ok(StackVisibility::Fading),
+			"invisible" => Ok(StackVisibility::Invisible),
+			_ => Err(format!("unknown stack visibility: {}", s))
+		}
+	}
+}
+
+// rules chosen for a game, kept by the frontend between games; the
+// randomizer and hold are kept per mode
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+	pub mode: GameMode,
+	pub sprint_lines: u32,
+	pub ultra_time: u32, // seconds
+	pub marathon_lines: u32,
+	pub marathon_level: u32, // the last level of a marathon
+	pub dig_rows: u32, // garbage rows to dig through
+	pub garbage_messiness: u32, // percent chance a garbage row's hole moves
+	pub rise_interval: u32, // ms before the first survival row rises
+	pub rotation: RotationSystemKind,
+	pub randomizer: RandomizerKind,
+	pub scoring: ScoringKind,
+	pub gravity: GravityKind,
+	pub lines_per_level: u32,
+	pub start_level: u32,
+	pub seed: Option<u64>, // a random seed for every game when unset
+	pub hold_enabled: bool,
+	pub ghost_enabled: bool,
+	pub stack: StackVisibility,
+	pub big: bool, // block cells cover BIG_SCALE x BIG_SCALE stage cells
+	pub lock_delay: u32, // ms
+	pub lock_reset: LockReset,
+	pub das: u32, // ms before a held left or right starts repeating
+	pub arr: u32, // ms between repeats, 0 moves straight to the wall
+	pub soft_drop_factor: u32, // gravity multiplier while down is held
+}
+
+impl Default for Settings {
+	fn default() -> Settings {
+		Settings {
+			mode: GameMode::Endless,
+			sprint_lines: 40,
+			ultra_time: 120,
+			marathon_lines: 150,
+			marathon_level: 15,
+			dig_rows: 10,
+			garbage_messiness: 100,
+			rise_interval: 5000,
+			rotation: RotationSystemKind::Super,
+			randomizer: RandomizerKind::Bag7,
+			scoring: ScoringKind::Guideline,
+			gravity: GravityKind::Guideline,
+			lines_per_level: 10,
+			start_level: 1,
+			seed: None,
+			hold_enabled: true,
+			ghost_enabled: true,
+			stack: StackVisibility::Shown,
+			big: false,
+			lock_delay: 500,
+			lock_reset: LockReset::Move,
+			das: 167,
+			arr: 33,
+			soft_drop_factor: 20
+		}
+	}
+}
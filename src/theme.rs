@@ -0,0 +1,141 @@
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use preferences::{Preferences, PreferencesMap};
+use tetris::*;
+
+
+pub type Color = [f32; 4];
+
+// a missing key in a theme file keeps the default colour
+pub struct Theme {
+	pub name: String,
+	pub font: String, // relative to the data folder
+	pub background: Color,
+	pub background_fill: Color,
+	pub grid: Color,
+	pub border: Color,
+	pub text: Color,
+	pub ghost: Color,
+	pub fill: [Color; 7], // per block kind, in the order of BLOCK_KINDS
+	pub block_border: [Color; 7],
+	pub garbage_fill: Color,
+	pub garbage_border: Color,
+}
+
+impl Default for Theme {
+	fn default() -> Theme {
+		Theme {
+			name: "classic".to_string(),
+			font: "font.ttf".to_string(),
+			background: [0.80, 0.85, 0.91, 1.0],
+			background_fill: [0.0, 0.0, 0.0, 0.1],
+			grid: [0.0, 0.0, 0.0, 0.1],
+			border: [0.0, 0.0, 0.0, 1.0],
+			text: [0.0, 0.0, 0.0, 1.0],
+			ghost: [0.0, 0.0, 0.0, 0.35],
+			fill: [[0.0, 0.0, 0.0, 1.0]; 7],
+			block_border: [[0.0, 0.0, 0.0, 1.0]; 7],
+			garbage_fill: [0.0, 0.0, 0.0, 1.0],
+			garbage_border: [0.0, 0.0, 0.0, 1.0],
+		}
+	}
+}
+
+impl Theme {
+	// theme files use the preferences format: a map of strings, colours as
+	// "#rrggbb" or "#rrggbbaa", block colours keyed by letter ("fill_T")
+	pub fn load(path: &Path) -> Result<Theme, String> {
+		let mut file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+		let map = PreferencesMap::<String>::load_from(&mut file)
+			.map_err(|e| format!("{}: {:?}", path.display(), e))?;
+
+		let name = match map.get("name") {
+			Some(name) => name.clone(),
+			None => path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned())
+		};
+
+		let mut theme = Theme { name, ..Theme::default() };
+
+		if let Some(font) = map.get("font") {
+			theme.font = font.clone();
+		}
+
+		let colors = vec![
+			("background", &mut theme.background),
+			("background_fill", &mut theme.background_fill),
+			("grid", &mut theme.grid),
+			("border", &mut theme.border),
+			("text", &mut theme.text),
+			("ghost", &mut theme.ghost),
+			("fill_garbage", &mut theme.garbage_fill),
+			("border_garbage", &mut theme.garbage_border)];
+
+		for (key, color) in colors {
+			if let Some(value) = map.get(key) {
+				*color = parse_color(value)?;
+			}
+		}
+
+		for (i, kind) in BLOCK_KINDS.iter().enumerate() {
+			if let Some(value) = map.get(&format!("fill_{}", kind.letter())) {
+				theme.fill[i] = parse_color(value)?;
+			}
+
+			if let Some(value) = map.get(&format!("border_{}", kind.letter())) {
+				theme.block_border[i] = parse_color(value)?;
+			}
+		}
+
+		Ok(theme)
+	}
+
+	// (fill, border)
+	pub fn block_colors(&self, kind: BlockKind) -> (Color, Color) {
+		(self.fill[kind as usize], self.block_border[kind as usize])
+	}
+
+	pub fn cell_colors(&self, cell: Cell) -> Option<(Color, Color)> {
+		match cell {
+			Cell::Empty => None,
+			Cell::Block(kind) => Some(self.block_colors(kind)),
+			Cell::Garbage => Some((self.garbage_fill, self.garbage_border))
+		}
+	}
+}
+
+// the colour with its alpha scaled, 0.0 is see-through
+pub fn faded(color: Color, alpha: f32) -> Color {
+	[color[0], color[1], color[2], color[3] * alpha]
+}
+
+pub fn parse_color(s: &str) -> Result<Color, String> {
+	let hex = s.trim().trim_start_matches('#');
+	if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+		return Err(format!("bad colour: {}", s));
+	}
+
+	let mut color = [1.0; 4];
+	for (i, channel) in color.iter_mut().enumerate().take(hex.len() / 2) {
+		let value = u8::from_str_radix(&hex[i*2..i*2 + 2], 16)
+			.map_err(|_| format!("bad colour: {}", s))?;
+		*channel = value as f32 / 255.0;
+	}
+
+	Ok(color)
+}
+
+// theme files in a folder, sorted by name
+pub fn find_themes(dir: &Path) -> Vec<PathBuf> {
+	let mut themes: Vec<PathBuf> = match fs::read_dir(dir) {
+		Ok(entries) => entries
+			.filter_map(|entry| entry.ok().map(|e| e.path()))
+			.filter(|path| path.extension() == Some(OsStr::new("cfg")))
+			.collect(),
+		Err(_) => Vec::new()
+	};
+
+	themes.sort();
+	themes
+}